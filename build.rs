@@ -1,11 +1,14 @@
 use std::{env, ffi::OsStr, fs::read_dir, path::PathBuf, process::Command};
 fn main() {
+    //Only the window draws with the shaders, and fxc only exists on Windows
+    if env::var_os("CARGO_CFG_WINDOWS").is_none() {
+        return;
+    }
     let root_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
     let mut shader_dir = PathBuf::from(root_dir);
     shader_dir.push("src\\shaders");
     read_dir(shader_dir)
         .unwrap()
-        .into_iter()
         .for_each(|shader| {
             let shader = shader.unwrap();
             if shader.path().extension().unwrap_or_else(|| OsStr::new("")) == OsStr::new("hlsl") {
@@ -28,7 +31,7 @@ fn main() {
                 let cmd = Command::new(
                     "C:\\Program Files (x86)\\Windows Kits\\10\\bin\\10.0.19041.0\\x64\\fxc.exe",
                 )
-                .args(&[
+                .args([
                     "/O2",
                     "/E",
                     "ShaderMain",
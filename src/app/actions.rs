@@ -0,0 +1,279 @@
+use super::*;
+use std::{collections::HashMap, fs};
+
+/// Everything the game reacts to, bound to keys or mouse buttons through `Bindings`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveForward,
+    MoveBack,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    Jump,
+    ToggleWalk,
+    BreakBlock,
+    PlaceBlock,
+    PopBlock,
+    Save,
+    Export,
+    Pause,
+}
+impl Action {
+    pub const ALL: [Action; 14] = [
+        Action::MoveForward,
+        Action::MoveBack,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::Jump,
+        Action::ToggleWalk,
+        Action::BreakBlock,
+        Action::PlaceBlock,
+        Action::PopBlock,
+        Action::Save,
+        Action::Export,
+        Action::Pause,
+    ];
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|a| format!("{:?}", a).eq_ignore_ascii_case(name))
+    }
+}
+
+//Windows virtual-key codes, mouse buttons included
+const KEY_NAMES: [(&str, u16); 18] = [
+    ("MouseLeft", 0x01),
+    ("MouseRight", 0x02),
+    ("MouseMiddle", 0x04),
+    ("Mouse4", 0x05),
+    ("Mouse5", 0x06),
+    ("Backspace", 0x08),
+    ("Tab", 0x09),
+    ("Enter", 0x0D),
+    ("Shift", 0x10),
+    ("Ctrl", 0x11),
+    ("Alt", 0x12),
+    ("Escape", 0x1B),
+    ("Space", 0x20),
+    ("Left", 0x25),
+    ("Up", 0x26),
+    ("Right", 0x27),
+    ("Down", 0x28),
+    ("Delete", 0x2E),
+];
+/// Virtual-key code for a key name: a letter, digit, `F1`-`F24` or one of `KEY_NAMES`.
+pub fn key_code(name: &str) -> Option<u16> {
+    if let Some(&(_, code)) = KEY_NAMES.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)) {
+        return Some(code);
+    }
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphanumeric() => Some(c.to_ascii_uppercase() as u16),
+        (Some('F'), Some(_)) | (Some('f'), Some(_)) => match name[1..].parse::<u16>() {
+            Ok(n) if (1..=24).contains(&n) => Some(0x70 + n - 1),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+//Holding an action that repeats fires it again after this long, then every interval
+pub const REPEAT_DELAY: f32 = 0.3;
+pub const REPEAT_INTERVAL: f32 = 0.15;
+
+/// Which keys trigger each action, any one of them is enough.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bindings {
+    keys: HashMap<Action, Vec<u16>>,
+}
+/// Action states for one frame, resolved from the key bitsets.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Actions {
+    held: [bool; Action::ALL.len()],
+    pressed: [bool; Action::ALL.len()],
+    released: [bool; Action::ALL.len()],
+    repeated: [bool; Action::ALL.len()],
+}
+impl Bindings {
+    pub fn new() -> Self {
+        Self {
+            keys: HashMap::new(),
+        }
+    }
+    pub fn keys(&self, action: Action) -> &[u16] {
+        self.keys.get(&action).map_or(&[], |k| k)
+    }
+    pub fn bind(&mut self, action: Action, keys: Vec<u16>) {
+        self.keys.insert(action, keys);
+    }
+    /// Reads lines like `MoveForward = W, Up` over the current bindings.
+    /// Blank lines and lines starting with `#` are skipped, `Action =` unbinds.
+    pub fn parse(&mut self, text: &str) -> Result<(), String> {
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (action, keys) = line
+                .split_once('=')
+                .ok_or(format!("Line {}: expected `Action = Key, Key`", n + 1))?;
+            let action = Action::from_name(action.trim()).ok_or(format!(
+                "Line {}: unknown action {}",
+                n + 1,
+                action.trim()
+            ))?;
+            let keys = keys
+                .split(',')
+                .map(str::trim)
+                .filter(|k| !k.is_empty())
+                .map(|k| key_code(k).ok_or(format!("Line {}: unknown key {}", n + 1, k)))
+                .collect::<Result<Vec<_>, _>>()?;
+            self.bind(action, keys);
+        }
+        Ok(())
+    }
+    /// Defaults with the file at `path` applied over them.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut bindings = Self::default();
+        bindings
+            .parse(&fs::read_to_string(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(bindings)
+    }
+    pub fn resolve(&self, input: &input::Input) -> Actions {
+        let mut actions = Actions::default();
+        for (i, &action) in Action::ALL.iter().enumerate() {
+            for &key in self.keys(action) {
+                actions.held[i] |= input.held(key);
+                actions.pressed[i] |= input.pressed(key);
+                actions.released[i] |= input.released(key);
+                actions.repeated[i] |= input.repeated(key, REPEAT_DELAY, REPEAT_INTERVAL);
+            }
+            //Still held through another key
+            actions.released[i] &= !actions.held[i];
+        }
+        actions
+    }
+}
+impl Actions {
+    pub fn held(&self, action: Action) -> bool {
+        self.held[action as usize]
+    }
+    /// One of the keys went down this frame.
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed[action as usize]
+    }
+    /// Let go of this frame, with none of its keys left down.
+    pub fn released(&self, action: Action) -> bool {
+        self.released[action as usize]
+    }
+    /// Pressed, or held long enough to fire again.
+    pub fn repeated(&self, action: Action) -> bool {
+        self.repeated[action as usize]
+    }
+}
+impl Default for Bindings {
+    fn default() -> Self {
+        let mut bindings = Self::new();
+        bindings
+            .parse(
+                "MoveForward = W
+                MoveBack = S
+                MoveLeft = A
+                MoveRight = D
+                MoveUp = E
+                MoveDown = Q
+                Jump = Space
+                ToggleWalk = F
+                BreakBlock = MouseLeft
+                PlaceBlock = MouseRight
+                PopBlock = L
+                Save = F5
+                Export = F6
+                Pause = Escape",
+            )
+            .unwrap();
+        bindings
+    }
+}
+
+#[test]
+fn actions_key_code() {
+    assert_eq!(key_code("w"), Some('W' as u16));
+    assert_eq!(key_code("7"), Some('7' as u16));
+    assert_eq!(key_code("space"), Some(0x20));
+    assert_eq!(key_code("F5"), Some(0x74));
+    assert_eq!(key_code("F24"), Some(0x87));
+    assert_eq!(key_code("F25"), None);
+    assert_eq!(key_code("MouseRight"), Some(0x02));
+    assert_eq!(key_code("Nope"), None);
+    assert_eq!(Action::from_name("breakblock"), Some(Action::BreakBlock));
+    assert_eq!(Action::from_name("Fly"), None);
+}
+#[test]
+fn actions_parse() {
+    let mut bindings = Bindings::default();
+    bindings
+        .parse("# arrows too\nMoveForward = W, Up\n\nJump =\nBreakBlock = MouseLeft, Ctrl")
+        .unwrap();
+    assert_eq!(bindings.keys(Action::MoveForward), &[0x57, 0x26]);
+    assert!(bindings.keys(Action::Jump).is_empty());
+    assert_eq!(bindings.keys(Action::BreakBlock), &[0x01, 0x11]);
+    assert_eq!(bindings.keys(Action::Pause), &[0x1B]);
+    assert!(bindings.parse("Fly = F").is_err());
+    assert!(bindings.parse("Jump = Hyperspace").is_err());
+    assert!(bindings
+        .parse("Jump Space")
+        .unwrap_err()
+        .starts_with("Line 1"));
+}
+#[test]
+fn actions_resolve() {
+    let mut bindings = Bindings::default();
+    bindings.parse("MoveForward = W, Up").unwrap();
+    let up = key_code("Up").unwrap();
+    let mut latch = input::KeyLatch::default();
+    let mut input = input::Input::new();
+    latch.set_key(true, 'W' as u16);
+    input.update(&mut latch, 0.1);
+    latch.set_key(false, 'W' as u16);
+    latch.set_key(true, up);
+    latch.set_key(true, 0x01);
+    latch.set_key(false, 0x01);
+    input.update(&mut latch, 0.1);
+    let actions = bindings.resolve(&input);
+    assert!(actions.held(Action::MoveForward));
+    //W was released but Up went down, so it still counts as a fresh press
+    assert!(actions.pressed(Action::MoveForward));
+    //A click shorter than a frame
+    assert!(actions.pressed(Action::BreakBlock));
+    assert!(!actions.held(Action::BreakBlock));
+    assert!(!actions.held(Action::Jump));
+    input.update(&mut latch, 0.1);
+    let actions = bindings.resolve(&input);
+    assert!(actions.held(Action::MoveForward));
+    assert!(!actions.pressed(Action::MoveForward));
+    //Up is still down, so letting go of W doesn't release MoveForward
+    latch.set_key(true, 'W' as u16);
+    input.update(&mut latch, 0.1);
+    latch.set_key(false, 'W' as u16);
+    input.update(&mut latch, 0.1);
+    let actions = bindings.resolve(&input);
+    assert!(!actions.released(Action::MoveForward));
+    latch.set_key(false, up);
+    input.update(&mut latch, 0.1);
+    assert!(bindings.resolve(&input).released(Action::MoveForward));
+    input.update(&mut latch, 0.1);
+    assert!(!bindings.resolve(&input).released(Action::MoveForward));
+    latch.set_key(true, 0x01);
+    input.update(&mut latch, 0.1);
+    assert!(bindings.resolve(&input).repeated(Action::BreakBlock));
+    input.update(&mut latch, REPEAT_DELAY / 2.0);
+    assert!(!bindings.resolve(&input).repeated(Action::BreakBlock));
+    input.update(&mut latch, REPEAT_DELAY);
+    assert!(bindings.resolve(&input).repeated(Action::BreakBlock));
+}
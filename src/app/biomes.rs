@@ -0,0 +1,153 @@
+use super::*;
+use blocks::BlockId;
+
+/// How far a decoration can reach sideways from the column it grows on.
+pub const MAX_REACH: i32 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Biome {
+    Plains,
+    Forest,
+    Desert,
+    Tundra,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decoration {
+    Tree,
+    Boulder,
+}
+#[derive(Debug, PartialEq)]
+pub struct Params {
+    /// Top block of each column
+    pub surface: BlockId,
+    /// The few blocks under the surface
+    pub filler: BlockId,
+    /// Chance per column to grow a tree
+    pub trees: f32,
+    /// Chance per column to drop a boulder
+    pub boulders: f32,
+}
+
+static PLAINS: Params = Params {
+    surface: blocks::GRASS,
+    filler: blocks::DIRT,
+    trees: 0.004,
+    boulders: 0.002,
+};
+static FOREST: Params = Params {
+    surface: blocks::GRASS,
+    filler: blocks::DIRT,
+    trees: 0.04,
+    boulders: 0.001,
+};
+static DESERT: Params = Params {
+    surface: blocks::SAND,
+    filler: blocks::SAND,
+    trees: 0.0,
+    boulders: 0.004,
+};
+static TUNDRA: Params = Params {
+    surface: blocks::SNOW,
+    filler: blocks::DIRT,
+    trees: 0.006,
+    boulders: 0.003,
+};
+
+impl Biome {
+    /// Picks the biome for a climate, both values in [0, 1].
+    pub fn from_climate(temperature: f32, humidity: f32) -> Self {
+        if temperature < 0.4 {
+            Self::Tundra
+        } else if temperature > 0.6 && humidity < 0.5 {
+            Self::Desert
+        } else if humidity > 0.5 {
+            Self::Forest
+        } else {
+            Self::Plains
+        }
+    }
+    pub fn params(self) -> &'static Params {
+        match self {
+            Self::Plains => &PLAINS,
+            Self::Forest => &FOREST,
+            Self::Desert => &DESERT,
+            Self::Tundra => &TUNDRA,
+        }
+    }
+    /// Decoration for a column given a uniform random value in [0, 1).
+    pub fn decoration(self, roll: f32) -> Option<Decoration> {
+        let params = self.params();
+        if roll < params.trees {
+            Some(Decoration::Tree)
+        } else if roll < params.trees + params.boulders {
+            Some(Decoration::Boulder)
+        } else {
+            None
+        }
+    }
+}
+impl Decoration {
+    /// Blocks relative to the air block above the surface, `variation` is in [0, 1).
+    pub fn blocks(self, variation: f32) -> Vec<(Coord<i32>, BlockId)> {
+        let mut list = vec![];
+        match self {
+            Self::Tree => {
+                let trunk = 4 + (variation * 3.0) as i32;
+                //First so the leaves around it can't take its place
+                for y in 0..trunk {
+                    list.push((Coord { x: 0, y, z: 0 }, blocks::WOOD));
+                }
+                for y in trunk - 2..=trunk + 1 {
+                    let radius = if y > trunk { 1 } else { MAX_REACH };
+                    for x in -radius..=radius {
+                        for z in -radius..=radius {
+                            //Rounds off the corners
+                            if x.abs() == radius && z.abs() == radius && radius > 1 {
+                                continue;
+                            }
+                            list.push((Coord { x, y, z }, blocks::LEAVES));
+                        }
+                    }
+                }
+            }
+            Self::Boulder => {
+                let radius = if variation < 0.5 { 1 } else { 2 };
+                for y in 0..radius {
+                    for x in -radius..=radius {
+                        for z in -radius..=radius {
+                            if x * x + y * y + z * z <= radius * radius {
+                                list.push((Coord { x, y, z }, blocks::STONE));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        list
+    }
+}
+
+#[test]
+fn biomes_from_climate() {
+    assert_eq!(Biome::from_climate(0.2, 0.9), Biome::Tundra);
+    assert_eq!(Biome::from_climate(0.8, 0.1), Biome::Desert);
+    assert_eq!(Biome::from_climate(0.8, 0.7), Biome::Forest);
+    assert_eq!(Biome::from_climate(0.5, 0.3), Biome::Plains);
+    assert_eq!(Biome::Forest.decoration(0.0), Some(Decoration::Tree));
+    assert_eq!(Biome::Desert.decoration(0.0), Some(Decoration::Boulder));
+    assert_eq!(Biome::Plains.decoration(0.99), None);
+}
+#[test]
+fn biomes_decorations_in_reach() {
+    for &decoration in &[Decoration::Tree, Decoration::Boulder] {
+        for &variation in &[0.0, 0.5, 0.99] {
+            let list = decoration.blocks(variation);
+            assert!(!list.is_empty());
+            for (pos, _) in list {
+                assert!(pos.x.abs() <= MAX_REACH && pos.z.abs() <= MAX_REACH);
+            }
+        }
+    }
+    let tree = Decoration::Tree.blocks(0.0);
+    assert_eq!(tree[0], (Coord { x: 0, y: 0, z: 0 }, blocks::WOOD));
+}
@@ -0,0 +1,183 @@
+pub type BlockId = u8;
+
+//Face order matches the quads emitted by `Cubes::to_vertices`
+pub const LEFT: usize = 0;
+pub const RIGHT: usize = 1;
+pub const FRONT: usize = 2;
+pub const BACK: usize = 3;
+pub const UP: usize = 4;
+pub const DOWN: usize = 5;
+
+pub const AIR: BlockId = 0;
+pub const STONE: BlockId = 1;
+pub const DIRT: BlockId = 2;
+pub const GRASS: BlockId = 3;
+pub const SAND: BlockId = 4;
+pub const GLASS: BlockId = 5;
+pub const WOOD: BlockId = 6;
+pub const LEAVES: BlockId = 7;
+pub const SNOW: BlockId = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Face {
+    pub color: [f32; 4],
+    pub texture: u16,
+}
+#[derive(Debug, PartialEq)]
+pub struct Block {
+    pub name: &'static str,
+    /// Blocks movement and raycasts.
+    pub solid: bool,
+    /// Lets the faces behind it show through.
+    pub transparent: bool,
+    /// Hides the faces of neighbouring blocks that touch it.
+    pub culls: bool,
+    pub faces: [Face; 6],
+}
+
+const fn uniform(color: [f32; 4], texture: u16) -> [Face; 6] {
+    let face = Face { color, texture };
+    [face, face, face, face, face, face]
+}
+const fn top_side_bottom(top: Face, side: Face, bottom: Face) -> [Face; 6] {
+    [side, side, side, side, top, bottom]
+}
+
+/// Every known block type, indexed by `BlockId`.
+pub static REGISTRY: [Block; 9] = [
+    Block {
+        name: "air",
+        solid: false,
+        transparent: true,
+        culls: false,
+        faces: uniform([0.0, 0.0, 0.0, 0.0], 0),
+    },
+    Block {
+        name: "stone",
+        solid: true,
+        transparent: false,
+        culls: true,
+        faces: uniform([0.5, 0.5, 0.5, 1.0], 1),
+    },
+    Block {
+        name: "dirt",
+        solid: true,
+        transparent: false,
+        culls: true,
+        faces: uniform([0.45, 0.3, 0.18, 1.0], 2),
+    },
+    Block {
+        name: "grass",
+        solid: true,
+        transparent: false,
+        culls: true,
+        faces: top_side_bottom(
+            Face {
+                color: [0.3, 0.65, 0.2, 1.0],
+                texture: 3,
+            },
+            Face {
+                color: [0.4, 0.45, 0.2, 1.0],
+                texture: 4,
+            },
+            Face {
+                color: [0.45, 0.3, 0.18, 1.0],
+                texture: 2,
+            },
+        ),
+    },
+    Block {
+        name: "sand",
+        solid: true,
+        transparent: false,
+        culls: true,
+        faces: uniform([0.86, 0.8, 0.55, 1.0], 5),
+    },
+    Block {
+        name: "glass",
+        solid: true,
+        transparent: true,
+        culls: false,
+        faces: uniform([0.75, 0.9, 0.95, 0.35], 6),
+    },
+    Block {
+        name: "wood",
+        solid: true,
+        transparent: false,
+        culls: true,
+        faces: top_side_bottom(
+            Face {
+                color: [0.6, 0.47, 0.28, 1.0],
+                texture: 8,
+            },
+            Face {
+                color: [0.4, 0.28, 0.15, 1.0],
+                texture: 7,
+            },
+            Face {
+                color: [0.6, 0.47, 0.28, 1.0],
+                texture: 8,
+            },
+        ),
+    },
+    Block {
+        name: "leaves",
+        solid: true,
+        transparent: true,
+        culls: false,
+        faces: uniform([0.2, 0.5, 0.15, 1.0], 9),
+    },
+    Block {
+        name: "snow",
+        solid: true,
+        transparent: false,
+        culls: true,
+        faces: uniform([0.95, 0.95, 0.97, 1.0], 10),
+    },
+];
+
+pub fn get(id: BlockId) -> Option<&'static Block> {
+    REGISTRY.get(id as usize)
+}
+pub fn by_name(name: &str) -> Option<BlockId> {
+    REGISTRY
+        .iter()
+        .position(|b| b.name == name)
+        .map(|id| id as BlockId)
+}
+pub fn is_solid(id: BlockId) -> bool {
+    get(id).is_some_and(|b| b.solid)
+}
+/// Whether the face of `block` that touches `neighbour` has to be drawn.
+pub fn face_visible(block: BlockId, neighbour: BlockId) -> bool {
+    if neighbour == AIR {
+        return true;
+    }
+    match get(neighbour) {
+        Some(n) if n.culls => false,
+        Some(n) => !(n.transparent && neighbour == block),
+        None => true,
+    }
+}
+
+#[test]
+fn blocks_registry() {
+    for (id, block) in REGISTRY.iter().enumerate() {
+        assert_eq!(by_name(block.name), Some(id as BlockId));
+    }
+    assert_eq!(get(STONE).unwrap().name, "stone");
+    assert_eq!(get(GLASS).unwrap().name, "glass");
+    assert!(get(200).is_none());
+    assert!(!is_solid(AIR));
+    assert!(is_solid(GLASS));
+}
+#[test]
+fn blocks_face_visible() {
+    assert!(face_visible(STONE, AIR));
+    assert!(!face_visible(STONE, STONE));
+    assert!(face_visible(STONE, GLASS));
+    assert!(!face_visible(GLASS, STONE));
+    assert!(!face_visible(GLASS, GLASS));
+    assert!(face_visible(GLASS, LEAVES));
+    assert!(face_visible(LEAVES, GLASS));
+}
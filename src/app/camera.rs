@@ -0,0 +1,135 @@
+use super::*;
+
+/// Keeps the view from flipping over at straight up or down.
+pub const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+
+/// How the mouse turns the view.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Settings {
+    /// Radians turned per mouse count
+    pub sensitivity: f32,
+    /// Moving the mouse up looks down
+    pub invert_y: bool,
+    /// 0 follows the mouse exactly, closer to 1 eases in more slowly
+    pub smoothing: f32,
+}
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            sensitivity: 0.002,
+            invert_y: false,
+            smoothing: 0.0,
+        }
+    }
+}
+
+/// Yaw and pitch accumulated from relative mouse motion, in radians. Yaw 0 looks down +z,
+/// positive yaw turns towards +x and positive pitch looks down.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    pub settings: Settings,
+    pub yaw: f32,
+    pub pitch: f32,
+    //Where the mouse has asked to look, `yaw`/`pitch` catch up to it when smoothing
+    target_yaw: f32,
+    target_pitch: f32,
+}
+impl Camera {
+    pub fn new(settings: Settings) -> Self {
+        Self {
+            settings,
+            yaw: 0.0,
+            pitch: 0.0,
+            target_yaw: 0.0,
+            target_pitch: 0.0,
+        }
+    }
+    /// Applies `(dx, dy)` mouse counts over a frame `delta_time` long.
+    pub fn turn(&mut self, dx: f32, dy: f32, delta_time: f32) {
+        let dy = if self.settings.invert_y { -dy } else { dy };
+        self.target_yaw += dx * self.settings.sensitivity;
+        self.target_pitch =
+            (self.target_pitch + dy * self.settings.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+        //Keep yaw small so it doesn't lose precision, shifting both keeps smoothing continuous
+        if self.target_yaw.abs() > PI {
+            let wrap = TAU * self.target_yaw.signum();
+            self.target_yaw -= wrap;
+            self.yaw -= wrap;
+        }
+        //Fraction of the way left to go each 60th of a second, so it's framerate independent
+        let smoothing = self.settings.smoothing.clamp(0.0, 0.99);
+        let t = if smoothing == 0.0 {
+            1.0
+        } else {
+            1.0 - smoothing.powf(delta_time * 60.0)
+        };
+        self.yaw += (self.target_yaw - self.yaw) * t;
+        self.pitch += (self.target_pitch - self.pitch) * t;
+    }
+    /// Unit vector the camera looks along.
+    pub fn direction(&self) -> Coord<f32> {
+        Coord {
+            x: self.pitch.cos() * self.yaw.sin(),
+            y: -self.pitch.sin(),
+            z: self.pitch.cos() * self.yaw.cos(),
+        }
+    }
+}
+
+#[cfg(test)]
+fn close(a: f32, b: f32) -> bool {
+    (a - b).abs() < 1e-4
+}
+#[test]
+fn camera_turn() {
+    let mut camera = Camera::new(Settings::default());
+    assert_eq!(
+        camera.direction(),
+        Coord {
+            x: 0.0,
+            y: 0.0,
+            z: 1.0
+        }
+    );
+    camera.turn(FRAC_PI_2 / 0.002, 0.0, 1.0 / 60.0);
+    let d = camera.direction();
+    assert!(close(d.x, 1.0) && close(d.y, 0.0) && close(d.z, 0.0));
+    //Moving the mouse down looks down, or up when inverted
+    camera.turn(0.0, 100.0, 1.0 / 60.0);
+    assert!(camera.direction().y < 0.0);
+    camera.settings.invert_y = true;
+    camera.turn(0.0, 200.0, 1.0 / 60.0);
+    assert!(camera.direction().y > 0.0);
+}
+#[test]
+fn camera_limits() {
+    let mut camera = Camera::new(Settings::default());
+    camera.turn(0.0, 1e6, 1.0 / 60.0);
+    assert_eq!(camera.pitch, MAX_PITCH);
+    //Coming back doesn't have to undo the overshoot
+    camera.turn(0.0, -1.0, 1.0 / 60.0);
+    assert!(camera.pitch < MAX_PITCH);
+    for _ in 0..1000 {
+        camera.turn(1000.0, 0.0, 1.0 / 60.0);
+        assert!(camera.yaw.abs() <= PI);
+    }
+}
+#[test]
+fn camera_smoothing() {
+    let mut camera = Camera::new(Settings {
+        smoothing: 0.5,
+        ..Settings::default()
+    });
+    camera.turn(500.0, 0.0, 1.0 / 60.0);
+    assert!(close(camera.yaw, 0.5));
+    camera.turn(0.0, 0.0, 1.0 / 60.0);
+    assert!(close(camera.yaw, 0.75));
+    //Same place after the same time, whatever the framerate
+    let mut slow = Camera::new(camera.settings);
+    slow.turn(500.0, 0.0, 2.0 / 60.0);
+    assert!(close(slow.yaw, 0.75));
+    for _ in 0..100 {
+        camera.turn(0.0, 0.0, 1.0 / 60.0);
+    }
+    assert!(close(camera.yaw, 1.0));
+}
@@ -0,0 +1,618 @@
+use super::*;
+use cubes::to_coord;
+use std::collections::{HashMap, HashSet};
+
+pub const CHUNK_SIZE: i32 = 16;
+pub const CHUNK_VOLUME: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+/// A fixed-size block of the world, laid out like `Cubes` (x, then z, then y).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chunk {
+    positions: Vec<u8>,
+    filled: usize,
+}
+/// First solid block along a ray.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hit {
+    pub block: Coord<i32>,
+    /// Outward normal of the face the ray entered through, zero if it started inside the block
+    pub normal: Coord<i32>,
+    pub distance: f32,
+}
+/// Sparse world storage: only chunks holding at least one block are allocated.
+#[derive(Debug, Default)]
+pub struct Chunks {
+    chunks: HashMap<Coord<i32>, Chunk>,
+    //Chunks whose mesh is out of date since the last `take_dirty`
+    dirty: HashSet<Coord<i32>>,
+}
+impl Chunk {
+    pub fn new() -> Self {
+        Self {
+            positions: vec![0; CHUNK_VOLUME],
+            filled: 0,
+        }
+    }
+    /// Wraps `CHUNK_VOLUME` blocks laid out like `positions`.
+    pub fn from_positions(positions: Vec<u8>) -> Self {
+        assert_eq!(positions.len(), CHUNK_VOLUME);
+        let filled = positions.iter().filter(|&&x| x != 0).count();
+        Self { positions, filled }
+    }
+    pub fn get(&self, idx: usize) -> u8 {
+        self.positions[idx]
+    }
+    pub fn set(&mut self, idx: usize, value: u8) -> u8 {
+        let old = self.positions[idx];
+        match (old, value) {
+            (0, 0) => {}
+            (0, _) => self.filled += 1,
+            (_, 0) => self.filled -= 1,
+            _ => {}
+        }
+        self.positions[idx] = value;
+        old
+    }
+    pub fn is_empty(&self) -> bool {
+        self.filled == 0
+    }
+    pub fn positions(&self) -> &[u8] {
+        &self.positions
+    }
+}
+impl Chunks {
+    pub fn new() -> Self {
+        Self {
+            chunks: HashMap::new(),
+            dirty: HashSet::new(),
+        }
+    }
+    pub fn get(&self, pos: Coord<i32>) -> u8 {
+        let (chunk, idx) = chunk_of(pos);
+        self.chunks.get(&chunk).map_or(0, |c| c.get(idx))
+    }
+    /// Sets the block at `pos` and returns the previous value.
+    /// Chunks are created on the first write and dropped once they are empty again.
+    pub fn set(&mut self, pos: Coord<i32>, value: u8) -> u8 {
+        let (chunk, idx) = chunk_of(pos);
+        let old = if value == 0 {
+            let old = match self.chunks.get_mut(&chunk) {
+                Some(c) => c.set(idx, 0),
+                None => return 0,
+            };
+            if self.chunks[&chunk].is_empty() {
+                self.chunks.remove(&chunk);
+            }
+            old
+        } else {
+            self.chunks.entry(chunk).or_default().set(idx, value)
+        };
+        if old != value {
+            //Faces and corner shading of the blocks around `pos` depend on it,
+            //those can be in the next chunk over
+            let (low, _) = chunk_of(
+                pos + Coord {
+                    x: -1,
+                    y: -1,
+                    z: -1,
+                },
+            );
+            let (high, _) = chunk_of(pos + Coord { x: 1, y: 1, z: 1 });
+            for y in low.y..=high.y {
+                for z in low.z..=high.z {
+                    for x in low.x..=high.x {
+                        self.dirty.insert(Coord { x, y, z });
+                    }
+                }
+            }
+        }
+        old
+    }
+    pub fn contains(&self, pos: Coord<i32>) -> bool {
+        self.get(pos) != 0
+    }
+    pub fn insert(&mut self, pos: Coord<i32>) -> bool {
+        self.set(pos, blocks::STONE) == 0
+    }
+    pub fn remove(&mut self, pos: Coord<i32>) -> bool {
+        self.set(pos, 0) > 0
+    }
+    /// Removes the last block in `Cubes` order, the highest one furthest along z then x.
+    pub fn pop(&mut self) -> Option<()> {
+        let last = self
+            .chunks
+            .iter()
+            .filter_map(|(&chunk, c)| {
+                let idx = c.positions.iter().rposition(|&x| x != 0)? as i32;
+                Some(
+                    chunk_origin(chunk)
+                        + Coord {
+                            x: idx % CHUNK_SIZE,
+                            y: idx / (CHUNK_SIZE * CHUNK_SIZE),
+                            z: idx / CHUNK_SIZE % CHUNK_SIZE,
+                        },
+                )
+            })
+            .max_by_key(|pos| (pos.y, pos.z, pos.x))?;
+        self.set(last, 0);
+        Some(())
+    }
+    /// Chunks edited since the last call, along with neighbours whose border faces changed.
+    /// Some of them can be empty by now.
+    pub fn take_dirty(&mut self) -> HashSet<Coord<i32>> {
+        std::mem::take(&mut self.dirty)
+    }
+    /// Copies every block of `cubes` into chunks.
+    pub fn from_cubes(cubes: &cubes::Cubes) -> Self {
+        let mut chunks = Self::new();
+        chunks.paste(cubes);
+        chunks
+    }
+    /// Writes the blocks of `cubes` over the world, the air in it leaves blocks as they are.
+    pub fn paste(&mut self, cubes: &cubes::Cubes) {
+        let (start, size) = (cubes.start(), cubes.size());
+        for (idx, &id) in cubes.positions().iter().enumerate() {
+            if id != 0 {
+                let pos = Coord {
+                    x: start.x + (idx % size.x) as i32,
+                    y: start.y + (idx / (size.x * size.z)) as i32,
+                    z: start.z + (idx / size.x % size.z) as i32,
+                };
+                self.set(pos, id);
+            }
+        }
+    }
+    /// Puts a whole chunk in place, an empty one removes it.
+    pub fn insert_chunk(&mut self, chunk: Coord<i32>, data: Chunk) {
+        if data.is_empty() {
+            self.chunks.remove(&chunk);
+        } else {
+            self.chunks.insert(chunk, data);
+        }
+        for y in -1..=1 {
+            for z in -1..=1 {
+                for x in -1..=1 {
+                    self.dirty.insert(chunk + Coord { x, y, z });
+                }
+            }
+        }
+    }
+    pub fn chunk(&self, chunk: Coord<i32>) -> Option<&Chunk> {
+        self.chunks.get(&chunk)
+    }
+    pub fn chunk_coords(&self) -> impl Iterator<Item = Coord<i32>> + '_ {
+        self.chunks.keys().copied()
+    }
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+    /// Copies one chunk into a `Cubes` placed at the chunk's world origin.
+    pub fn to_cubes(&self, chunk: Coord<i32>) -> Option<cubes::Cubes> {
+        let c = self.chunks.get(&chunk)?;
+        Some(cubes::Cubes::from_dense(
+            c.positions.clone(),
+            chunk_origin(chunk),
+            Coord {
+                x: CHUNK_SIZE as usize,
+                y: CHUNK_SIZE as usize,
+                z: CHUNK_SIZE as usize,
+            },
+        ))
+    }
+    /// Lowest and highest chunk coordinates in use, `None` without any chunks.
+    fn chunk_bounds(&self) -> Option<(Coord<i32>, Coord<i32>)> {
+        let mut coords = self.chunks.keys();
+        let first = *coords.next()?;
+        Some(coords.fold((first, first), |(mut low, mut high), c| {
+            for a in 0..3 {
+                low[a] = low[a].min(c[a]);
+                high[a] = high[a].max(c[a]);
+            }
+            (low, high)
+        }))
+    }
+    /// Copies every block into one `Cubes` just big enough to hold them.
+    pub fn to_dense(&self) -> cubes::Cubes {
+        let (low, high) = match self.chunk_bounds() {
+            Some(bounds) => bounds,
+            None => return cubes::Cubes::new(),
+        };
+        let size = CHUNK_SIZE as usize;
+        let len = Coord {
+            x: (high.x - low.x + 1) as usize * size,
+            y: (high.y - low.y + 1) as usize * size,
+            z: (high.z - low.z + 1) as usize * size,
+        };
+        let mut positions = vec![0; len.x * len.y * len.z];
+        for (&chunk, c) in &self.chunks {
+            let (off_x, off_y, off_z) = (
+                (chunk.x - low.x) as usize * size,
+                (chunk.y - low.y) as usize * size,
+                (chunk.z - low.z) as usize * size,
+            );
+            for y in 0..size {
+                for z in 0..size {
+                    let from = (z + y * size) * size;
+                    let to = (z + off_z + (y + off_y) * len.z) * len.x + off_x;
+                    positions[to..to + size].copy_from_slice(&c.positions[from..from + size]);
+                }
+            }
+        }
+        let mut cubes = cubes::Cubes::from_dense(positions, chunk_origin(low), len);
+        cubes.shrink();
+        cubes
+    }
+    /// Meshes a single chunk. Blocks in the neighbouring chunks still hide and shade its
+    /// border faces, so the chunk meshes together look like meshing the whole world at once.
+    pub fn to_vertices(
+        &self,
+        chunk: Coord<i32>,
+        meshing: cubes::Meshing,
+        vert_len: usize,
+        idx_len: usize,
+    ) -> mesh::Mesh {
+        if !self.chunks.contains_key(&chunk) {
+            return mesh::Mesh::default();
+        }
+        //One block around the chunk is all face culling and occlusion look at
+        let origin = chunk_origin(chunk);
+        let start = origin
+            + Coord {
+                x: -1,
+                y: -1,
+                z: -1,
+            };
+        let padded = CHUNK_SIZE + 2;
+        let mut positions = Vec::with_capacity((padded * padded * padded) as usize);
+        for y in 0..padded {
+            for z in 0..padded {
+                for x in 0..padded {
+                    positions.push(self.get(start + Coord { x, y, z }));
+                }
+            }
+        }
+        let size = CHUNK_SIZE as usize;
+        cubes::Cubes::from_dense(
+            positions,
+            start,
+            Coord {
+                x: padded as usize,
+                y: padded as usize,
+                z: padded as usize,
+            },
+        )
+        .to_vertices_within(
+            meshing,
+            origin,
+            Coord {
+                x: size,
+                y: size,
+                z: size,
+            },
+            vert_len,
+            idx_len,
+        )
+    }
+    /// Amanatides-Woo grid traversal, visiting every block the ray passes through in order.
+    pub fn raycast(
+        &self,
+        origin: Coord<f32>,
+        direction: Coord<f32>,
+        max_distance: f32,
+    ) -> Option<Hit> {
+        let length =
+            (direction.x * direction.x + direction.y * direction.y + direction.z * direction.z)
+                .sqrt();
+        if length == 0. || !length.is_finite() {
+            return None;
+        }
+        let (low, high) = self.chunk_bounds()?;
+        let origin = [origin.x, origin.y, origin.z];
+        let direction = [
+            direction.x / length,
+            direction.y / length,
+            direction.z / length,
+        ];
+        let mut cell = origin.map(|o| o.floor() as i32);
+        let mut step = [0; 3];
+        let mut t_max = [f32::INFINITY; 3];
+        let mut t_delta = [f32::INFINITY; 3];
+        for axis in 0..3 {
+            if direction[axis] > 0. {
+                step[axis] = 1;
+                t_delta[axis] = 1. / direction[axis];
+                t_max[axis] = (cell[axis] as f32 + 1. - origin[axis]) * t_delta[axis];
+            } else if direction[axis] < 0. {
+                step[axis] = -1;
+                t_delta[axis] = -1. / direction[axis];
+                t_max[axis] = (origin[axis] - cell[axis] as f32) * t_delta[axis];
+            }
+        }
+        let (min, max) = (
+            chunk_origin(low),
+            chunk_origin(high + Coord { x: 1, y: 1, z: 1 }),
+        );
+        let mut normal = [0; 3];
+        let mut distance = 0.;
+        while distance <= max_distance {
+            if blocks::is_solid(self.get(to_coord(cell))) {
+                return Some(Hit {
+                    block: to_coord(cell),
+                    normal: to_coord(normal),
+                    distance,
+                });
+            }
+            //Nothing more to hit once the ray is outside every chunk and moving away from it
+            if (0..3)
+                .any(|a| (cell[a] < min[a] && step[a] <= 0) || (cell[a] >= max[a] && step[a] >= 0))
+            {
+                return None;
+            }
+            let axis = if t_max[0] < t_max[1] {
+                if t_max[0] < t_max[2] {
+                    0
+                } else {
+                    2
+                }
+            } else if t_max[1] < t_max[2] {
+                1
+            } else {
+                2
+            };
+            distance = t_max[axis];
+            cell[axis] += step[axis];
+            t_max[axis] += t_delta[axis];
+            normal = [0; 3];
+            normal[axis] = -step[axis];
+        }
+        None
+    }
+}
+pub fn chunk_of(pos: Coord<i32>) -> (Coord<i32>, usize) {
+    let chunk = Coord {
+        x: pos.x.div_euclid(CHUNK_SIZE),
+        y: pos.y.div_euclid(CHUNK_SIZE),
+        z: pos.z.div_euclid(CHUNK_SIZE),
+    };
+    let (x, y, z) = (
+        pos.x.rem_euclid(CHUNK_SIZE),
+        pos.y.rem_euclid(CHUNK_SIZE),
+        pos.z.rem_euclid(CHUNK_SIZE),
+    );
+    let idx = x + z * CHUNK_SIZE + y * CHUNK_SIZE * CHUNK_SIZE;
+    (chunk, idx as usize)
+}
+pub fn chunk_origin(chunk: Coord<i32>) -> Coord<i32> {
+    Coord {
+        x: chunk.x * CHUNK_SIZE,
+        y: chunk.y * CHUNK_SIZE,
+        z: chunk.z * CHUNK_SIZE,
+    }
+}
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+//Two worlds are equal when they hold the same blocks, whatever is left to remesh
+impl PartialEq for Chunks {
+    fn eq(&self, other: &Self) -> bool {
+        self.chunks == other.chunks
+    }
+}
+impl Eq for Chunks {}
+
+#[test]
+#[rustfmt::skip::macros(assert_eq)]
+fn chunks_get_set() {
+    let mut chunks = Chunks::new();
+    assert_eq!(chunks.get(Coord { x: 3, y: -20, z: 40 }), 0);
+    assert_eq!(chunks.set(Coord { x: 3, y: -20, z: 40 }, 1), 0);
+    assert_eq!(chunks.set(Coord { x: -1, y: 0, z: 0 }, 1), 0);
+    assert_eq!(chunks.get(Coord { x: 3, y: -20, z: 40 }), 1);
+    assert_eq!(chunks.get(Coord { x: -1, y: 0, z: 0 }), 1);
+    assert_eq!(chunks.get(Coord { x: 0, y: 0, z: 0 }), 0);
+    assert_eq!(chunks.len(), 2);
+    assert!(chunks.chunk(Coord { x: 0, y: -2, z: 2 }).is_some());
+    assert!(chunks.chunk(Coord { x: -1, y: 0, z: 0 }).is_some());
+}
+#[test]
+fn chunks_drop_empty() {
+    let mut chunks = Chunks::new();
+    chunks.set(Coord { x: 5, y: 5, z: 5 }, 1);
+    chunks.set(Coord { x: 6, y: 5, z: 5 }, 1);
+    assert_eq!(chunks.set(Coord { x: 5, y: 5, z: 5 }, 0), 1);
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(chunks.set(Coord { x: 6, y: 5, z: 5 }, 0), 1);
+    assert!(chunks.is_empty());
+    assert_eq!(chunks.set(Coord { x: 6, y: 5, z: 5 }, 0), 0);
+    assert!(chunks.is_empty());
+}
+#[test]
+#[rustfmt::skip::macros(assert_eq)]
+fn chunks_to_vertices() {
+    let mut chunks = Chunks::new();
+    chunks.set(Coord { x: 17, y: -1, z: 2 }, 1);
+    chunks.set(Coord { x: 0, y: 0, z: 0 }, 1);
+    let mesh = chunks.to_vertices(Coord { x: 1, y: -1, z: 0 }, cubes::Meshing::Naive, 8, 36);
+    assert_eq!(mesh.vertices.len(), 24);
+    assert_eq!(mesh.indices.len(), 36);
+    assert_eq!(mesh.vertices[0].position, Coord { x: 17., y: -1., z: 2. });
+    assert_eq!(mesh.vertices[5].position, Coord { x: 18., y: 0., z: 3. });
+    assert!(chunks
+        .to_vertices(Coord { x: 4, y: 4, z: 4 }, cubes::Meshing::Naive, 0, 0)
+        .is_empty());
+}
+#[test]
+#[rustfmt::skip::macros(assert_eq)]
+fn chunks_raycast() {
+    let mut cubes = Chunks::from_cubes(
+        &cubes::Cubes::new_list(vec![
+            Coord { x: 0, y: 0, z: 5 },
+            Coord { x: 3, y: -2, z: -4 },
+        ])
+        .unwrap(),
+    );
+    cubes.set(Coord { x: 0, y: 0, z: 3 }, blocks::GLASS);
+    let eye = Coord {
+        x: 0.5,
+        y: 0.5,
+        z: -10.,
+    };
+    assert_eq!(cubes.raycast(eye, Coord { x: 0., y: 0., z: 1. }, 100.), Some(Hit {
+        block: Coord { x: 0, y: 0, z: 3 },
+        normal: Coord { x: 0, y: 0, z: -1 },
+        distance: 13.,
+    }));
+    assert_eq!(cubes.raycast(eye, Coord { x: 0., y: 0., z: 1. }, 12.), None);
+    assert_eq!(cubes.raycast(eye, Coord { x: 0., y: 0., z: -1. }, 100.), None);
+    assert_eq!(cubes.raycast(eye, Coord { x: 0., y: 1., z: 0. }, 100.), None);
+    //Coming from above, not normalized
+    let hit = cubes
+        .raycast(
+            Coord {
+                x: 3.5,
+                y: 10.,
+                z: -3.5,
+            },
+            Coord {
+                x: 0.,
+                y: -4.,
+                z: 0.,
+            },
+            20.,
+        )
+        .unwrap();
+    assert_eq!(hit.block, Coord { x: 3, y: -2, z: -4 });
+    assert_eq!(hit.normal, Coord { x: 0, y: 1, z: 0 });
+    assert_eq!(hit.distance, 11.);
+    //Diagonal into the side of a block
+    let hit = cubes
+        .raycast(
+            Coord {
+                x: -2.,
+                y: 0.5,
+                z: 3.5,
+            },
+            Coord {
+                x: 1.,
+                y: 0.,
+                z: 1.,
+            },
+            10.,
+        )
+        .unwrap();
+    assert_eq!(hit.block, Coord { x: 0, y: 0, z: 5 });
+    assert_eq!(hit.normal, Coord { x: -1, y: 0, z: 0 });
+    assert!((hit.distance - 2. * 2f32.sqrt()).abs() < 1e-5);
+    //Starting inside a block
+    let hit = cubes
+        .raycast(
+            Coord {
+                x: 0.2,
+                y: 0.7,
+                z: 5.5,
+            },
+            Coord {
+                x: 1.,
+                y: 1.,
+                z: 0.,
+            },
+            10.,
+        )
+        .unwrap();
+    assert_eq!(hit.block, Coord { x: 0, y: 0, z: 5 });
+    assert_eq!(hit.normal, Coord { x: 0, y: 0, z: 0 });
+    assert_eq!(hit.distance, 0.);
+    assert_eq!(cubes.raycast(eye, Coord { x: 0., y: 0., z: 0. }, 100.), None);
+    assert_eq!(Chunks::new().raycast(eye, Coord { x: 0., y: 0., z: 1. }, 100.), None);
+}
+#[test]
+fn chunks_dirty() {
+    let mut chunks = Chunks::new();
+    chunks.set(Coord { x: 5, y: 5, z: 5 }, 1);
+    let dirty: Vec<_> = chunks.take_dirty().into_iter().collect();
+    assert_eq!(dirty, vec![Coord { x: 0, y: 0, z: 0 }]);
+    assert!(chunks.take_dirty().is_empty());
+    //Setting the same value again changes nothing
+    chunks.set(Coord { x: 5, y: 5, z: 5 }, 1);
+    assert!(chunks.take_dirty().is_empty());
+    //A corner block touches the 7 chunks around that corner too
+    chunks.set(Coord { x: 0, y: 15, z: 0 }, 1);
+    let dirty = chunks.take_dirty();
+    assert_eq!(dirty.len(), 8);
+    assert!(dirty.contains(&Coord { x: -1, y: 1, z: -1 }));
+    assert!(!dirty.contains(&Coord { x: 1, y: 0, z: 0 }));
+}
+#[test]
+fn chunks_pop_and_dense() {
+    let cubes = cubes::Cubes::default_scene();
+    let mut chunks = Chunks::from_cubes(&cubes);
+    assert_eq!(chunks.to_dense(), cubes);
+    chunks.set(Coord { x: 40, y: -3, z: 2 }, blocks::GLASS);
+    let dense = chunks.to_dense();
+    assert_eq!(dense.get(Coord { x: 40, y: -3, z: 2 }), blocks::GLASS);
+    assert_eq!(Chunks::from_cubes(&dense), chunks);
+    //Same order as `Cubes::pop`
+    let mut dense = cubes::Cubes::default_scene();
+    while dense.pop().is_some() {
+        chunks.pop().unwrap();
+        if chunks.contains(Coord { x: 40, y: -3, z: 2 }) {
+            chunks.remove(Coord { x: 40, y: -3, z: 2 });
+        }
+        assert_eq!(chunks.to_dense(), dense);
+    }
+    assert!(chunks.is_empty());
+    assert!(chunks.pop().is_none());
+    assert_eq!(chunks.to_dense(), cubes::Cubes::new());
+}
+#[test]
+fn chunks_mesh_like_the_whole_world() {
+    let cubes = terrain::Generator::new(4).fill_cubes(
+        Coord {
+            x: -20,
+            y: 0,
+            z: -20,
+        },
+        Coord {
+            x: 40,
+            y: 40,
+            z: 40,
+        },
+    );
+    let mut chunks = Chunks::from_cubes(&cubes);
+    let coords = chunks.take_dirty();
+    assert!(coords.len() > chunks.len());
+    for &meshing in &[cubes::Meshing::Naive, cubes::Meshing::Greedy] {
+        let whole = cubes.to_vertices(meshing, 0, 0);
+        let meshes: Vec<_> = coords
+            .iter()
+            .map(|&c| chunks.to_vertices(c, meshing, 0, 0))
+            .collect();
+        let merged = mesh::Mesh::merge(&meshes);
+        if meshing == cubes::Meshing::Naive {
+            //Every single face is the same, shading included
+            let corners = |mesh: &mesh::Mesh| {
+                let mut corners: Vec<_> = mesh
+                    .vertices
+                    .iter()
+                    .map(|v| {
+                        let (p, n) = (v.position, v.normal);
+                        (p.x, p.y, p.z, n.x, n.y, n.z, v.ao)
+                    })
+                    .collect();
+                corners.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                corners
+            };
+            assert_eq!(corners(&merged), corners(&whole));
+        } else {
+            //Quads stop at chunk borders but cover the same faces
+            assert!(merged.indices.len() >= whole.indices.len());
+            assert_eq!(cubes::surface_area(merged), cubes::surface_area(whole));
+        }
+    }
+}
@@ -1,4 +1,96 @@
 use super::*;
+use mesh::{Mesh, Vertex};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Meshing {
+    /// One quad per visible block face
+    Naive,
+    /// Coplanar visible faces of the same block type merged into rectangles
+    Greedy,
+}
+//Corners of each face of the unit cube, in `blocks::LEFT..=blocks::DOWN` order
+#[rustfmt::skip]
+const FACES: [[Coord<f32>; 4]; 6] = [
+    [Coord { x: 0., y: 0., z: 0. }, Coord { x: 0., y: 0., z: 1. }, Coord { x: 0., y: 1., z: 1. }, Coord { x: 0., y: 1., z: 0. }],
+    [Coord { x: 1., y: 1., z: 0. }, Coord { x: 1., y: 1., z: 1. }, Coord { x: 1., y: 0., z: 1. }, Coord { x: 1., y: 0., z: 0. }],
+    [Coord { x: 0., y: 0., z: 0. }, Coord { x: 0., y: 1., z: 0. }, Coord { x: 1., y: 1., z: 0. }, Coord { x: 1., y: 0., z: 0. }],
+    [Coord { x: 0., y: 0., z: 1. }, Coord { x: 1., y: 0., z: 1. }, Coord { x: 1., y: 1., z: 1. }, Coord { x: 0., y: 1., z: 1. }],
+    [Coord { x: 0., y: 1., z: 1. }, Coord { x: 1., y: 1., z: 1. }, Coord { x: 1., y: 1., z: 0. }, Coord { x: 0., y: 1., z: 0. }],
+    [Coord { x: 0., y: 0., z: 0. }, Coord { x: 1., y: 0., z: 0. }, Coord { x: 1., y: 0., z: 1. }, Coord { x: 0., y: 0., z: 1. }],
+];
+//(normal axis, u axis, v axis, normal direction) of each face, axes as x: 0, y: 1, z: 2
+const FACE_AXES: [(usize, usize, usize, i32); 6] = [
+    (0, 2, 1, -1),
+    (0, 2, 1, 1),
+    (2, 0, 1, -1),
+    (2, 0, 1, 1),
+    (1, 0, 2, 1),
+    (1, 0, 2, -1),
+];
+const QUAD: [u32; 6] = [0, 1, 2, 0, 2, 3];
+
+//Same quad split along its other diagonal
+const QUAD_FLIPPED: [u32; 6] = [1, 2, 3, 1, 3, 0];
+
+pub fn to_coord(p: [i32; 3]) -> Coord<i32> {
+    Coord {
+        x: p[0],
+        y: p[1],
+        z: p[2],
+    }
+}
+/// Classic voxel ambient occlusion of a face corner: 0 is fully occluded, 3 is open.
+pub fn vertex_ao(side1: bool, side2: bool, corner: bool) -> u8 {
+    if side1 && side2 {
+        0
+    } else {
+        3 - side1 as u8 - side2 as u8 - corner as u8
+    }
+}
+/// Appends one face of `size` blocks, starting at the block at `origin`, with its own 4 verticies.
+#[allow(clippy::too_many_arguments)]
+fn push_quad(
+    verticies: &mut Vec<Vertex>,
+    indicies: &mut Vec<u32>,
+    id: blocks::BlockId,
+    face: usize,
+    origin: [f32; 3],
+    size: [f32; 3],
+    ao: [u8; 4],
+) {
+    let (n, u, v, dir) = FACE_AXES[face];
+    let mut normal = [0.; 3];
+    normal[n] = dir as f32;
+    let color = blocks::get(id).map_or([1., 0., 1., 1.], |b| b.faces[face].color);
+    let shift = verticies.len() as u32;
+    //Split along the brighter diagonal so the occlusion gradient stays symmetric
+    let quad = if ao[0] + ao[2] >= ao[1] + ao[3] {
+        QUAD
+    } else {
+        QUAD_FLIPPED
+    };
+    indicies.extend(quad.iter().map(|i| i + shift));
+    verticies.extend(FACES[face].iter().zip(ao.iter()).map(|(c, &ao)| {
+        let c = [c.x, c.y, c.z];
+        Vertex {
+            position: Coord {
+                x: c[0] * size[0] + origin[0],
+                y: c[1] * size[1] + origin[1],
+                z: c[2] * size[2] + origin[2],
+            },
+            ao: ao as f32,
+            normal: Coord {
+                x: normal[0],
+                y: normal[1],
+                z: normal[2],
+            },
+            uv: [c[u] * size[u], (1. - c[v]) * size[v]],
+            color,
+            block: id as u32,
+        }
+    }));
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct Cubes {
     positions: Vec<u8>,
@@ -21,6 +113,18 @@ impl Cubes {
             len_z: 0,
         }
     }
+    /// The handful of cubes shown when there is no world to load.
+    pub fn default_scene() -> Self {
+        Self::new_list(vec![
+            Coord { x: -3, y: 0, z: 0 },
+            Coord { x: -2, y: 0, z: 0 },
+            Coord { x: -2, y: 1, z: 1 },
+            Coord { x: -1, y: 0, z: 0 },
+            Coord { x: -1, y: 0, z: 1 },
+            Coord { x: -1, y: 1, z: 1 },
+        ])
+        .unwrap()
+    }
     pub fn new_list(list: Vec<Coord<i32>>) -> Option<Self> {
         let mut positions = vec![0];
         let list_iter = &list;
@@ -46,7 +150,7 @@ impl Cubes {
             let pos_y = (item.y - start_y) * stride_y;
             let pos_z = (item.z - start_z) * stride_z;
             let pos = pos_x + pos_y + pos_z;
-            positions[pos as usize] = 1;
+            positions[pos as usize] = blocks::STONE;
         }
         Some(Self {
             positions,
@@ -58,71 +162,193 @@ impl Cubes {
             len_z: len_z as usize,
         })
     }
-    pub fn to_vertices(&self, vert_len: usize, idx_len: usize) -> (Vec<Coord<f32>>, Vec<u16>) {
+    pub fn from_dense(positions: Vec<u8>, start: Coord<i32>, len: Coord<usize>) -> Self {
+        assert_eq!(positions.len(), len.x * len.y * len.z);
+        Self {
+            positions,
+            start_x: start.x,
+            start_y: start.y,
+            start_z: start.z,
+            len_x: len.x,
+            len_y: len.y,
+            len_z: len.z,
+        }
+    }
+    pub fn to_vertices(&self, meshing: Meshing, vert_len: usize, idx_len: usize) -> Mesh {
+        self.to_vertices_within(meshing, self.start(), self.size(), vert_len, idx_len)
+    }
+    /// Meshes only the blocks in the box of `len` at `start`. The blocks around it still
+    /// hide and shade its faces, which lets a chunk be meshed on its own.
+    pub fn to_vertices_within(
+        &self,
+        meshing: Meshing,
+        start: Coord<i32>,
+        len: Coord<usize>,
+        vert_len: usize,
+        idx_len: usize,
+    ) -> Mesh {
         if self.positions.is_empty() {
-            return (vec![], vec![]);
+            return Mesh::default();
+        }
+        if meshing == Meshing::Greedy {
+            return self.to_vertices_greedy(start, len, vert_len, idx_len);
         }
-        #[rustfmt::skip]
-        const VERTICIES: [Coord<f32>; 8] = [
-            Coord { x: 0., y: 0., z: 0. },
-            Coord { x: 0., y: 0., z: 1. },
-            Coord { x: 0., y: 1., z: 1. },
-            Coord { x: 0., y: 1., z: 0. },
-            Coord { x: 1., y: 1., z: 0. },
-            Coord { x: 1., y: 1., z: 1. },
-            Coord { x: 1., y: 0., z: 1. },
-            Coord { x: 1., y: 0., z: 0. },
-        ];
-        const INDICIES: [u16; 36] = [
-            0, 1, 2, 0, 2, 3, //Left
-            4, 5, 6, 4, 6, 7, //Right
-            0, 3, 4, 0, 4, 7, //Front
-            1, 6, 5, 1, 5, 2, //Back
-            2, 4, 3, 2, 5, 4, //Up
-            0, 6, 1, 0, 7, 6, //Down
-        ];
-        let stride_z = self.len_x;
-        let stride_y = stride_z * self.len_z;
-        let len_vec = stride_y * self.len_y;
         let mut indicies_all = Vec::with_capacity(idx_len);
-        let mut shift = 0;
         let mut verticies_all = Vec::with_capacity(vert_len);
-        for cube in self.positions.iter().enumerate() {
-            if *cube.1 == 1 {
-                if cube.0 % stride_z == 0 || self.positions[cube.0 - 1] == 0 {
-                    indicies_all.extend(INDICIES[0..6].iter().map(|i| i + shift));
-                }
-                if cube.0 % stride_z == stride_z - 1 || self.positions[cube.0 + 1] == 0 {
-                    indicies_all.extend(INDICIES[6..12].iter().map(|i| i + shift));
-                }
-                if cube.0 % stride_y < stride_z || self.positions[cube.0 - stride_z] == 0 {
-                    indicies_all.extend(INDICIES[12..18].iter().map(|i| i + shift));
+        for y in start.y..start.y + len.y as i32 {
+            for z in start.z..start.z + len.z as i32 {
+                for x in start.x..start.x + len.x as i32 {
+                    let pos = Coord { x, y, z };
+                    let id = self.get(pos);
+                    if id == blocks::AIR {
+                        continue;
+                    }
+                    for (face, &(n, _, _, dir)) in FACE_AXES.iter().enumerate() {
+                        let mut next = pos;
+                        next[n] += dir;
+                        if blocks::face_visible(id, self.get(next)) {
+                            push_quad(
+                                &mut verticies_all,
+                                &mut indicies_all,
+                                id,
+                                face,
+                                [x as f32, y as f32, z as f32],
+                                [1.; 3],
+                                self.face_ao(pos, face),
+                            );
+                        }
+                    }
                 }
-                if cube.0 % stride_y >= stride_y - stride_z
-                    || self.positions[cube.0 + stride_z] == 0
-                {
-                    indicies_all.extend(INDICIES[18..24].iter().map(|i| i + shift));
-                }
-                if cube.0 >= len_vec - stride_y || self.positions[cube.0 + stride_y] == 0 {
-                    indicies_all.extend(INDICIES[24..30].iter().map(|i| i + shift));
+            }
+        }
+        Mesh::new(verticies_all, indicies_all)
+    }
+    fn to_vertices_greedy(
+        &self,
+        start: Coord<i32>,
+        len: Coord<usize>,
+        vert_len: usize,
+        idx_len: usize,
+    ) -> Mesh {
+        let mut indicies_all = Vec::with_capacity(idx_len);
+        let mut verticies_all = Vec::with_capacity(vert_len);
+        let none = (blocks::AIR, [0; 4]);
+        for (face, &(n, u, v, dir)) in FACE_AXES.iter().enumerate() {
+            let (len_u, len_v) = (len[u], len[v]);
+            let mut mask = vec![none; len_u * len_v];
+            for k in 0..len[n] {
+                //Which block type shows this face at each (u, v) of the slice, and how occluded
+                //its corners are, since only faces with the same shading can be merged
+                for j in 0..len_v {
+                    for i in 0..len_u {
+                        let mut pos = [0; 3];
+                        pos[n] = start[n] + k as i32;
+                        pos[u] = start[u] + i as i32;
+                        pos[v] = start[v] + j as i32;
+                        let id = self.get(to_coord(pos));
+                        let mut next = pos;
+                        next[n] += dir;
+                        mask[i + j * len_u] = if id != blocks::AIR
+                            && blocks::face_visible(id, self.get(to_coord(next)))
+                        {
+                            (id, self.face_ao(to_coord(pos), face))
+                        } else {
+                            none
+                        };
+                    }
                 }
-                if cube.0 < stride_y || self.positions[cube.0 - stride_y] == 0 {
-                    indicies_all.extend(INDICIES[30..36].iter().map(|i| i + shift));
+                for j in 0..len_v {
+                    let mut i = 0;
+                    while i < len_u {
+                        let quad = mask[i + j * len_u];
+                        if quad == none {
+                            i += 1;
+                            continue;
+                        }
+                        let mut w = 1;
+                        while i + w < len_u && mask[i + w + j * len_u] == quad {
+                            w += 1;
+                        }
+                        let mut h = 1;
+                        while j + h < len_v && (i..i + w).all(|x| mask[x + (j + h) * len_u] == quad)
+                        {
+                            h += 1;
+                        }
+                        for y in j..j + h {
+                            mask[i + y * len_u..i + w + y * len_u].fill(none);
+                        }
+                        let mut origin = [0.; 3];
+                        origin[n] = (start[n] + k as i32) as f32;
+                        origin[u] = (start[u] + i as i32) as f32;
+                        origin[v] = (start[v] + j as i32) as f32;
+                        let mut size = [1.; 3];
+                        size[u] = w as f32;
+                        size[v] = h as f32;
+                        push_quad(
+                            &mut verticies_all,
+                            &mut indicies_all,
+                            quad.0,
+                            face,
+                            origin,
+                            size,
+                            quad.1,
+                        );
+                        i += w;
+                    }
                 }
-                shift += 8;
-                let x = ((cube.0 % stride_z) as i32 + self.start_x) as f32;
-                let z = (((cube.0 % stride_y) / stride_z) as i32 + self.start_y) as f32;
-                let y = ((cube.0 / stride_y) as i32 + self.start_z) as f32;
-                let coord = Coord { x, y, z };
-                verticies_all.extend(VERTICIES.iter().map(|v| *v + coord));
             }
         }
-        (verticies_all, indicies_all)
+        Mesh::new(verticies_all, indicies_all)
+    }
+    fn occludes(&self, pos: [i32; 3]) -> bool {
+        blocks::get(self.get(to_coord(pos))).is_some_and(|b| b.culls)
+    }
+    /// Ambient occlusion of the 4 corners of a block face, in `FACES` order.
+    pub fn face_ao(&self, pos: Coord<i32>, face: usize) -> [u8; 4] {
+        let (n, u, v, dir) = FACE_AXES[face];
+        let mut base = [pos.x, pos.y, pos.z];
+        base[n] += dir;
+        let mut ao = [3; 4];
+        for (i, c) in FACES[face].iter().enumerate() {
+            let c = [c.x, c.y, c.z];
+            let mut side1 = base;
+            side1[u] += if c[u] == 0. { -1 } else { 1 };
+            let mut side2 = base;
+            side2[v] += if c[v] == 0. { -1 } else { 1 };
+            let mut corner = side1;
+            corner[v] = side2[v];
+            ao[i] = vertex_ao(
+                self.occludes(side1),
+                self.occludes(side2),
+                self.occludes(corner),
+            );
+        }
+        ao
+    }
+    pub fn start(&self) -> Coord<i32> {
+        Coord {
+            x: self.start_x,
+            y: self.start_y,
+            z: self.start_z,
+        }
+    }
+    pub fn size(&self) -> Coord<usize> {
+        Coord {
+            x: self.len_x,
+            y: self.len_y,
+            z: self.len_z,
+        }
+    }
+    pub fn positions(&self) -> &[u8] {
+        &self.positions
+    }
+    pub fn is_empty(&self) -> bool {
+        self.positions.iter().all(|&x| x == 0)
     }
     pub fn pop(&mut self) -> Option<()> {
-        let idx = self.positions.iter().rposition(|&x| x == 1)?;
-        self.positions[idx as usize] = 0;
-        //self.shrink();
+        let idx = self.positions.iter().rposition(|&x| x != 0)?;
+        self.positions[idx] = 0;
+        self.shrink();
         Some(())
     }
     /*pub fn pop_start(&mut self) -> Option<()> {
@@ -131,79 +357,140 @@ impl Cubes {
         //self.shrink();
         Some(())
     }*/
-    pub fn remove(&mut self, x: usize, y: usize, z: usize) -> bool {
-        let pos = x + self.len_x * z + self.len_x * self.len_z * y;
-        let state = self.positions[pos];
-        self.positions[pos] = 0;
-        state > 0
-    } /*
-      pub fn shrink(&mut self) {
-          if self.positions.iter().all(|&x| x == 0) {
-              self.positions = vec![];
-              self.len_x = 0;
-              self.len_y = 0;
-              self.len_z = 0;
-              return;
-          }
-          let mut left = 0;
-          let mut right = 0;
-          let mut down = self.len_y;
-          let mut up = 0;
-          let mut front = self.len_z;
-          let mut back = 0;
-          let mut stop_left = false;
-          for i in 0..self.len_x {
-              let mut stop_front = false;
-              let mut cube_x = false;
-              let mut this_z = 0;
-              for j in 0..self.len_z {
-                  let mut cube_z = false;
-                  for k in 0..self.len_y {
-                      let idx = (j + k * self.len_z) * self.len_x + i;
-                      if self.positions[idx] != 0 {
-                          cube_x = true;
-                          cube_z = true;
-                          up = up.max(k + 1);
-                          down = down.min(k);
-                      }
-                  }
-                  if cube_z {
-                      back = back.max(j + 1);
-                      stop_front = true;
-                  } else if !stop_front {
-                      this_z += 1;
-                  }
-              }
-              if cube_x {
-                  right = right.max(i + 1);
-                  stop_left = true;
-              } else if !stop_left {
-                  left += 1;
-              }
-              front = front.min(this_z);
-          }
-          let mut resized = vec![];
-          let stride_y = self.len_x * self.len_z;
-          println!("{}-{},{}-{},{}-{}", left, right, front, back, down, up);
-          for item in self.positions.iter().enumerate() {
-              if item.0 % self.len_x >= left
-                  && item.0 % self.len_x < right
-                  && item.0 % stride_y >= front * self.len_x
-                  && item.0 % stride_y < back * self.len_x
-                  && item.0 >= down * stride_y
-                  && item.0 < up * stride_y
-              {
-                  resized.push(*item.1);
-              }
-          }
-          self.positions = resized;
-          self.len_x = right - left;
-          self.len_y = up - down;
-          self.len_z = back - front;
-          self.start_x += left as i32;
-          self.start_y += down as i32;
-          self.start_z += front as i32;
-      }*/
+    fn index(&self, pos: Coord<i32>) -> Option<usize> {
+        let x = pos.x.checked_sub(self.start_x)?;
+        let y = pos.y.checked_sub(self.start_y)?;
+        let z = pos.z.checked_sub(self.start_z)?;
+        if x < 0
+            || y < 0
+            || z < 0
+            || x as usize >= self.len_x
+            || y as usize >= self.len_y
+            || z as usize >= self.len_z
+        {
+            return None;
+        }
+        Some(x as usize + self.len_x * z as usize + self.len_x * self.len_z * y as usize)
+    }
+    pub fn get(&self, pos: Coord<i32>) -> u8 {
+        self.index(pos).map_or(0, |idx| self.positions[idx])
+    }
+    pub fn contains(&self, pos: Coord<i32>) -> bool {
+        self.get(pos) != 0
+    }
+    /// Sets the block at `pos` and returns the previous value.
+    /// The grid grows to fit writes outside of it and shrinks when a border block is cleared.
+    pub fn set(&mut self, pos: Coord<i32>, value: u8) -> u8 {
+        match self.index(pos) {
+            Some(idx) => {
+                let old = self.positions[idx];
+                self.positions[idx] = value;
+                if value == 0 && old != 0 && self.on_border(pos) {
+                    self.shrink();
+                }
+                old
+            }
+            None if value == 0 => 0,
+            None => {
+                self.grow(pos);
+                let idx = self.index(pos).unwrap();
+                self.positions[idx] = value;
+                0
+            }
+        }
+    }
+    pub fn insert(&mut self, pos: Coord<i32>) -> bool {
+        self.set(pos, blocks::STONE) == 0
+    }
+    pub fn remove(&mut self, pos: Coord<i32>) -> bool {
+        self.set(pos, 0) > 0
+    }
+    fn on_border(&self, pos: Coord<i32>) -> bool {
+        pos.x == self.start_x
+            || pos.y == self.start_y
+            || pos.z == self.start_z
+            || pos.x == self.start_x + self.len_x as i32 - 1
+            || pos.y == self.start_y + self.len_y as i32 - 1
+            || pos.z == self.start_z + self.len_z as i32 - 1
+    }
+    fn grow(&mut self, pos: Coord<i32>) {
+        if self.positions.is_empty() {
+            *self = Self::from_dense(vec![0], pos, Coord { x: 1, y: 1, z: 1 });
+            return;
+        }
+        let start = Coord {
+            x: self.start_x.min(pos.x),
+            y: self.start_y.min(pos.y),
+            z: self.start_z.min(pos.z),
+        };
+        let len = Coord {
+            x: ((self.start_x + self.len_x as i32).max(pos.x + 1) - start.x) as usize,
+            y: ((self.start_y + self.len_y as i32).max(pos.y + 1) - start.y) as usize,
+            z: ((self.start_z + self.len_z as i32).max(pos.z + 1) - start.z) as usize,
+        };
+        let mut grown = Self::from_dense(vec![0; len.x * len.y * len.z], start, len);
+        let (off_x, off_y, off_z) = (
+            (self.start_x - start.x) as usize,
+            (self.start_y - start.y) as usize,
+            (self.start_z - start.z) as usize,
+        );
+        for y in 0..self.len_y {
+            for z in 0..self.len_z {
+                let from = (z + y * self.len_z) * self.len_x;
+                let to = (z + off_z + (y + off_y) * len.z) * len.x + off_x;
+                grown.positions[to..to + self.len_x]
+                    .copy_from_slice(&self.positions[from..from + self.len_x]);
+            }
+        }
+        *self = grown;
+    }
+    pub fn shrink(&mut self) {
+        if self.positions.iter().all(|&x| x == 0) {
+            *self = Self::new();
+            return;
+        }
+        let mut left = self.len_x;
+        let mut right = 0;
+        let mut down = self.len_y;
+        let mut up = 0;
+        let mut front = self.len_z;
+        let mut back = 0;
+        for k in 0..self.len_y {
+            for j in 0..self.len_z {
+                for i in 0..self.len_x {
+                    let idx = (j + k * self.len_z) * self.len_x + i;
+                    if self.positions[idx] != 0 {
+                        left = left.min(i);
+                        right = right.max(i + 1);
+                        front = front.min(j);
+                        back = back.max(j + 1);
+                        down = down.min(k);
+                        up = up.max(k + 1);
+                    }
+                }
+            }
+        }
+        let mut resized = vec![];
+        let stride_y = self.len_x * self.len_z;
+        for item in self.positions.iter().enumerate() {
+            if item.0 % self.len_x >= left
+                && item.0 % self.len_x < right
+                && item.0 % stride_y >= front * self.len_x
+                && item.0 % stride_y < back * self.len_x
+                && item.0 >= down * stride_y
+                && item.0 < up * stride_y
+            {
+                resized.push(*item.1);
+            }
+        }
+        self.positions = resized;
+        self.len_x = right - left;
+        self.len_y = up - down;
+        self.len_z = back - front;
+        self.start_x += left as i32;
+        self.start_y += down as i32;
+        self.start_z += front as i32;
+    }
 }
 
 #[test]
@@ -260,97 +547,214 @@ fn cube_to_vertices() {
         Coord { x: -1, y: 1, z: 1 },
     ])
     .unwrap();
+    let mesh = cubes.to_vertices(Meshing::Naive, 104, 156);
+    //5 + 3 + 5 + 4 + 4 + 5 visible faces, none of them sharing verticies
+    assert_eq!(mesh.vertices.len(), 26 * 4);
+    for (f, quad) in mesh.indices.to_vec().chunks(6).enumerate() {
+        let shift = f as u32 * 4;
+        assert!(quad == QUAD.map(|i| i + shift) || quad == QUAD_FLIPPED.map(|i| i + shift));
+    }
+    //First cube (-3, 0, 0): its right face is hidden by (-2, 0, 0)
+    let faces = [
+        blocks::LEFT,
+        blocks::FRONT,
+        blocks::BACK,
+        blocks::UP,
+        blocks::DOWN,
+    ];
+    for (quad, &face) in mesh.vertices.chunks(4).zip(faces.iter()) {
+        for (vertex, corner) in quad.iter().zip(FACES[face].iter()) {
+            assert_eq!(vertex.position, *corner + Coord { x: -3., y: 0., z: 0. });
+            assert_eq!(vertex.block, blocks::STONE as u32);
+        }
+    }
+    assert_eq!(mesh.vertices[0].normal, Coord { x: -1., y: 0., z: 0. });
+    assert_eq!(mesh.vertices[4].normal, Coord { x: 0., y: 0., z: -1. });
+    assert_eq!(mesh.vertices[8].normal, Coord { x: 0., y: 0., z: 1. });
+    assert_eq!(mesh.vertices[12].normal, Coord { x: 0., y: 1., z: 0. });
+    assert_eq!(mesh.vertices[16].normal, Coord { x: 0., y: -1., z: 0. });
+    assert_eq!(mesh.vertices[12].uv, [0., 0.]);
+    assert_eq!(mesh.vertices[14].uv, [1., 1.]);
+    assert_eq!(mesh.vertices[12].color, blocks::REGISTRY[1].faces[blocks::UP].color);
+}
+
+#[test]
+#[rustfmt::skip::macros(assert_eq)]
+fn cube_face_ao() {
+    assert_eq!(vertex_ao(false, false, false), 3);
+    assert_eq!(vertex_ao(false, false, true), 2);
+    assert_eq!(vertex_ao(true, false, true), 1);
+    assert_eq!(vertex_ao(true, true, false), 0);
+    let mut cubes = Cubes::new();
+    for x in -1..=1 {
+        for z in -1..=1 {
+            cubes.insert(Coord { x, y: 0, z });
+        }
+    }
+    let center = Coord { x: 0, y: 0, z: 0 };
+    assert_eq!(cubes.face_ao(center, blocks::UP), [3; 4]);
+    //A wall along +x darkens the +x corners of the top face
+    cubes.insert(Coord { x: 1, y: 1, z: 0 });
+    //FACES[UP] corners: (0,1,1), (1,1,1), (1,1,0), (0,1,0)
+    assert_eq!(cubes.face_ao(center, blocks::UP), [3, 2, 2, 3]);
+    //Plus a wall along -z: the corner between them is fully occluded
+    cubes.insert(Coord { x: 0, y: 1, z: -1 });
+    assert_eq!(cubes.face_ao(center, blocks::UP), [3, 2, 0, 2]);
+    //Glass doesn't occlude
+    cubes.set(Coord { x: 0, y: 1, z: -1 }, blocks::GLASS);
+    assert_eq!(cubes.face_ao(center, blocks::UP), [3, 2, 2, 3]);
+
+    //Index of the quad of the center's top face
+    let top = |mesh: &Mesh| {
+        mesh.vertices
+            .chunks(4)
+            .position(|q| {
+                q[0].position
+                    == Coord {
+                        x: 0.,
+                        y: 1.,
+                        z: 1.,
+                    }
+                    && q[0].normal.y == 1.
+            })
+            .unwrap()
+    };
+    let mesh = cubes.to_vertices(Meshing::Naive, 0, 0);
+    let up = top(&mesh);
+    let ao: Vec<_> = mesh.vertices[up * 4..up * 4 + 4]
+        .iter()
+        .map(|v| v.ao)
+        .collect();
+    assert_eq!(ao, vec![3., 2., 2., 3.]);
+    //Equal diagonal sums keep the default split
+    let shift = up as u32 * 4;
+    assert_eq!(mesh.indices.to_vec()[up * 6..up * 6 + 6], QUAD.map(|i| i + shift));
+    //[3, 2, 0, 2]: the dark corner must not be on the shared diagonal
+    cubes.set(Coord { x: 0, y: 1, z: -1 }, blocks::STONE);
+    let mesh = cubes.to_vertices(Meshing::Naive, 0, 0);
+    let up = top(&mesh);
+    let shift = up as u32 * 4;
+    assert_eq!(mesh.indices.to_vec()[up * 6..up * 6 + 6], QUAD_FLIPPED.map(|i| i + shift));
+}
+#[test]
+fn cube_to_vertices_culling() {
+    let mut cubes = Cubes::new();
+    cubes.set(Coord { x: 0, y: 0, z: 0 }, blocks::STONE);
+    cubes.set(Coord { x: 1, y: 0, z: 0 }, blocks::GLASS);
     assert_eq!(
-        cubes.to_vertices(384, 312),
-        (vec![
-            Coord { x: 0., y: 0., z: 0. },//1.
-            Coord { x: 0., y: 0., z: 1. },//1.
-            Coord { x: 0., y: 1., z: 1. },//1.
-            Coord { x: 0., y: 1., z: 0. },//1.
-            Coord { x: 1., y: 1., z: 0. },//1.
-            Coord { x: 1., y: 1., z: 1. },//1.
-            Coord { x: 1., y: 0., z: 1. },//1.
-            Coord { x: 1., y: 0., z: 0. },//1.
-            Coord { x: 0.+1., y: 0., z: 0. },//2.
-            Coord { x: 0.+1., y: 0., z: 1. },//2.
-            Coord { x: 0.+1., y: 1., z: 1. },//2.
-            Coord { x: 0.+1., y: 1., z: 0. },//2.
-            Coord { x: 1.+1., y: 1., z: 0. },//2.
-            Coord { x: 1.+1., y: 1., z: 1. },//2.
-            Coord { x: 1.+1., y: 0., z: 1. },//2.
-            Coord { x: 1.+1., y: 0., z: 0. },//2.
-            Coord { x: 0.+2., y: 0., z: 0. },//4.
-            Coord { x: 0.+2., y: 0., z: 1. },//4.
-            Coord { x: 0.+2., y: 1., z: 1. },//4.
-            Coord { x: 0.+2., y: 1., z: 0. },//4.
-            Coord { x: 1.+2., y: 1., z: 0. },//4.
-            Coord { x: 1.+2., y: 1., z: 1. },//4.
-            Coord { x: 1.+2., y: 0., z: 1. },//4.
-            Coord { x: 1.+2., y: 0., z: 0. },//4.
-            Coord { x: 0.+2., y: 0., z: 0.+1. },//5.
-            Coord { x: 0.+2., y: 0., z: 1.+1. },//5.
-            Coord { x: 0.+2., y: 1., z: 1.+1. },//5.
-            Coord { x: 0.+2., y: 1., z: 0.+1. },//5.
-            Coord { x: 1.+2., y: 1., z: 0.+1. },//5.
-            Coord { x: 1.+2., y: 1., z: 1.+1. },//5.
-            Coord { x: 1.+2., y: 0., z: 1.+1. },//5.
-            Coord { x: 1.+2., y: 0., z: 0.+1. },//5.
-            Coord { x: 0.+1., y: 0.+1., z: 0. },//3.
-            Coord { x: 0.+1., y: 0.+1., z: 1. },//3.
-            Coord { x: 0.+1., y: 1.+1., z: 1. },//3.
-            Coord { x: 0.+1., y: 1.+1., z: 0. },//3.
-            Coord { x: 1.+1., y: 1.+1., z: 0. },//3.
-            Coord { x: 1.+1., y: 1.+1., z: 1. },//3.
-            Coord { x: 1.+1., y: 0.+1., z: 1. },//3.
-            Coord { x: 1.+1., y: 0.+1., z: 0. },//3.
-            Coord { x: 0.+2., y: 0.+1., z: 0.+1. },//6.
-            Coord { x: 0.+2., y: 0.+1., z: 1.+1. },//6.
-            Coord { x: 0.+2., y: 1.+1., z: 1.+1. },//6.
-            Coord { x: 0.+2., y: 1.+1., z: 0.+1. },//6.
-            Coord { x: 1.+2., y: 1.+1., z: 0.+1. },//6.
-            Coord { x: 1.+2., y: 1.+1., z: 1.+1. },//6.
-            Coord { x: 1.+2., y: 0.+1., z: 1.+1. },//6.
-            Coord { x: 1.+2., y: 0.+1., z: 0.+1. },//6.
-        ],vec![
-            0, 1, 2, 0, 2, 3, //1
-            //4, 5, 6, 4, 6, 7, //1
-            0, 3, 4, 0, 4, 7, //1
-            1, 6, 5, 1, 5, 2, //1
-            2, 4, 3, 2, 5, 4, //1
-            0, 6, 1, 0, 7, 6, //1
-            //0+8, 1+8, 2+8, 0+8, 2+8, 3+8, //2
-            //4+8, 5+8, 6+8, 4+8, 6+8, 7+8, //2
-            0+8, 3+8, 4+8, 0+8, 4+8, 7+8, //2
-            1+8, 6+8, 5+8, 1+8, 5+8, 2+8, //2
-            //2+8, 4+8, 3+8, 2+8, 5+8, 4+8, //2
-            0+8, 6+8, 1+8, 0+8, 7+8, 6+8, //2
-            //0+16, 1+16, 2+16, 0+16, 2+16, 3+16, //3
-            4+16, 5+16, 6+16, 4+16, 6+16, 7+16, //3
-            0+16, 3+16, 4+16, 0+16, 4+16, 7+16, //3
-            //1+16, 6+16, 5+16, 1+16, 5+16, 2+16, //3
-            2+16, 4+16, 3+16, 2+16, 5+16, 4+16, //3
-            0+16, 6+16, 1+16, 0+16, 7+16, 6+16, //3
-            0+24, 1+24, 2+24, 0+24, 2+24, 3+24, //4
-            4+24, 5+24, 6+24, 4+24, 6+24, 7+24, //4
-            //0+24, 3+24, 4+24, 0+24, 4+24, 7+24, //4
-            1+24, 6+24, 5+24, 1+24, 5+24, 2+24, //4
-            //2+24, 4+24, 3+24, 2+24, 5+24, 4+24, //4
-            0+24, 6+24, 1+24, 0+24, 7+24, 6+24, //4
-            0+32, 1+32, 2+32, 0+32, 2+32, 3+32, //5
-            4+32, 5+32, 6+32, 4+32, 6+32, 7+32, //5
-            0+32, 3+32, 4+32, 0+32, 4+32, 7+32, //5
-            1+32, 6+32, 5+32, 1+32, 5+32, 2+32, //5
-            2+32, 4+32, 3+32, 2+32, 5+32, 4+32, //5
-            //0+32, 6+32, 1+32, 0+32, 7+32, 6+32, //5
-            0+40, 1+40, 2+40, 0+40, 2+40, 3+40, //6
-            4+40, 5+40, 6+40, 4+40, 6+40, 7+40, //6
-            0+40, 3+40, 4+40, 0+40, 4+40, 7+40, //6
-            1+40, 6+40, 5+40, 1+40, 5+40, 2+40, //6
-            2+40, 4+40, 3+40, 2+40, 5+40, 4+40, //6
-            //0+40, 6+40, 1+40, 0+40, 7+40, 6+40, //6
-        ])
+        cubes.to_vertices(Meshing::Naive, 16, 72).indices.len(),
+        36 + 30
+    );
+    cubes.set(Coord { x: 2, y: 0, z: 0 }, blocks::GLASS);
+    assert_eq!(
+        cubes.to_vertices(Meshing::Naive, 24, 108).indices.len(),
+        36 + 24 + 30
+    );
+    cubes.set(Coord { x: 1, y: 0, z: 0 }, blocks::STONE);
+    assert_eq!(
+        cubes.to_vertices(Meshing::Naive, 24, 108).indices.len(),
+        30 + 30 + 30
+    );
+}
+#[cfg(test)]
+pub fn surface_area(mesh: Mesh) -> f32 {
+    let verticies: Vec<_> = mesh.vertices.iter().map(|v| v.position).collect();
+    mesh.indices
+        .to_vec()
+        .chunks(3)
+        .map(|t| {
+            let (a, b, c) = (
+                verticies[t[0] as usize],
+                verticies[t[1] as usize],
+                verticies[t[2] as usize],
+            );
+            let (u, v) = (
+                Coord {
+                    x: b.x - a.x,
+                    y: b.y - a.y,
+                    z: b.z - a.z,
+                },
+                Coord {
+                    x: c.x - a.x,
+                    y: c.y - a.y,
+                    z: c.z - a.z,
+                },
+            );
+            let cross = Coord {
+                x: u.y * v.z - u.z * v.y,
+                y: u.z * v.x - u.x * v.z,
+                z: u.x * v.y - u.y * v.x,
+            };
+            (cross.x * cross.x + cross.y * cross.y + cross.z * cross.z).sqrt() / 2.
+        })
+        .sum()
+}
+#[test]
+fn cube_to_vertices_greedy() {
+    let mut cubes = Cubes::new();
+    for x in -3..5 {
+        for z in 0..6 {
+            for y in 0..3 {
+                cubes.insert(Coord { x, y, z });
+            }
+        }
+    }
+    cubes.set(Coord { x: 0, y: 3, z: 2 }, blocks::GLASS);
+    cubes.set(Coord { x: 1, y: 3, z: 2 }, blocks::GLASS);
+    cubes.remove(Coord { x: 2, y: 2, z: 2 });
+    cubes.remove(Coord { x: -3, y: 0, z: 0 });
+    let naive = cubes.to_vertices(Meshing::Naive, 0, 0);
+    let greedy = cubes.to_vertices(Meshing::Greedy, 0, 0);
+    assert!(greedy.indices.len() * 4 < naive.indices.len());
+    assert_eq!(surface_area(naive), surface_area(greedy));
+
+    let single = Cubes::new_list(vec![Coord { x: 7, y: -2, z: 1 }]).unwrap();
+    let greedy = single.to_vertices(Meshing::Greedy, 0, 0);
+    assert_eq!((greedy.vertices.len(), greedy.indices.len()), (24, 36));
+    assert_eq!(
+        surface_area(single.to_vertices(Meshing::Naive, 0, 0)),
+        surface_area(greedy)
     );
 }
+#[test]
+fn cube_to_vertices_large() {
+    //Isolated cubes share no faces, so every one of them costs 24 verticies
+    let checkerboard = |count: i32| {
+        let mut cubes = Cubes::new();
+        for i in 0..count {
+            let (x, z, y) = (i % 32, (i / 32) % 32, i / 1024);
+            cubes.insert(Coord {
+                x: x * 2 + (z + y) % 2,
+                y: y * 2,
+                z: z * 2,
+            });
+        }
+        cubes
+    };
+    let mesh = checkerboard(2730).to_vertices(Meshing::Naive, 0, 0);
+    assert_eq!(mesh.vertices.len(), 65520);
+    assert!(matches!(mesh.indices, mesh::Indices::U16(_)));
+    assert_eq!(mesh.indices.to_vec().into_iter().max(), Some(65519));
+
+    let mesh = checkerboard(2731).to_vertices(Meshing::Naive, 0, 0);
+    assert_eq!(mesh.vertices.len(), 65544);
+    assert!(matches!(mesh.indices, mesh::Indices::U32(_)));
+    assert_eq!(mesh.indices.to_vec().into_iter().max(), Some(65543));
+
+    let cubes = checkerboard(20000);
+    let mesh = cubes.to_vertices(Meshing::Naive, 0, 0);
+    assert!(matches!(mesh.indices, mesh::Indices::U32(_)));
+    assert_eq!(mesh.indices.len(), 20000 * 36);
+    let indicies = mesh.indices.to_vec();
+    assert!(indicies.iter().all(|&i| (i as usize) < mesh.vertices.len()));
+    assert_eq!(
+        indicies[indicies.len() - 36..].iter().min(),
+        Some(&(19999 * 24))
+    );
+    let greedy = cubes.to_vertices(Meshing::Greedy, 0, 0);
+    assert!(matches!(greedy.indices, mesh::Indices::U32(_)));
+    assert_eq!(surface_area(mesh), surface_area(greedy));
+}
 
 #[test]
 #[rustfmt::skip::macros(assert)]
@@ -362,7 +766,59 @@ fn cube_remove() {
         Coord {x: -4, y: -2, z: -1},
         Coord {x: 0, y: 0, z: -2},
         Coord {x: -1, y: -1, z: 1},
-    ]).unwrap().remove(2, 2, 2))
+    ]).unwrap().remove(Coord {x: -3, y: -1, z: 0}))
+}
+#[test]
+#[rustfmt::skip::macros(assert_eq)]
+fn cube_insert_grows() {
+    let mut cubes = Cubes::new();
+    assert!(!cubes.contains(Coord { x: 0, y: 0, z: 0 }));
+    assert!(cubes.insert(Coord { x: 1, y: 2, z: 3 }));
+    assert!(!cubes.insert(Coord { x: 1, y: 2, z: 3 }));
+    assert!(cubes.insert(Coord { x: -1, y: 0, z: 4 }));
+    assert_eq!(cubes.set(Coord {x: 0, y: 1, z: 3}, 1), 0);
+    assert_eq!(cubes, Cubes::new_list(vec![
+        Coord {x: 1, y: 2, z: 3},
+        Coord {x: -1, y: 0, z: 4},
+        Coord {x: 0, y: 1, z: 3},
+    ]).unwrap());
+    assert_eq!(cubes.get(Coord {x: -1, y: 0, z: 4}), 1);
+    assert_eq!(cubes.get(Coord {x: 100, y: -100, z: 4}), 0);
+    assert_eq!(cubes.set(Coord {x: 100, y: -100, z: 4}, 0), 0);
+}
+#[test]
+#[rustfmt::skip::macros(assert_eq)]
+fn cube_remove_shrinks() {
+    let mut cubes = Cubes::new_list(vec![
+        Coord {
+            x: -5,
+            y: -3,
+            z: -2,
+        },
+        Coord { x: -2, y: -3, z: 2 },
+        Coord { x: -3, y: -1, z: 0 },
+        Coord { x: 0, y: 0, z: -2 },
+    ])
+    .unwrap();
+    assert!(cubes.remove(Coord {
+        x: -5,
+        y: -3,
+        z: -2
+    }));
+    assert!(!cubes.remove(Coord {
+        x: -5,
+        y: -3,
+        z: -2
+    }));
+    assert!(cubes.remove(Coord { x: 0, y: 0, z: -2 }));
+    assert_eq!(cubes, Cubes::new_list(vec![
+        Coord {x: -2, y: -3, z: 2},
+        Coord {x: -3, y: -1, z: 0},
+    ]).unwrap());
+    assert!(cubes.remove(Coord { x: -2, y: -3, z: 2 }));
+    assert!(cubes.remove(Coord { x: -3, y: -1, z: 0 }));
+    assert_eq!(cubes, Cubes::new());
+    assert!(cubes.pop().is_none());
 }
 
 impl Default for Cubes {
@@ -370,3 +826,11 @@ impl Default for Cubes {
         Self::new()
     }
 }
+#[test]
+fn cube_is_empty() {
+    let mut cubes = Cubes::default_scene();
+    assert!(!cubes.is_empty());
+    while cubes.pop().is_some() {}
+    assert!(cubes.is_empty());
+    assert!(Cubes::new().is_empty());
+}
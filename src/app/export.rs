@@ -0,0 +1,207 @@
+use super::*;
+use mesh::{Indices, Mesh};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+//Both formats are right-handed, so z is flipped. Mirroring also turns the clockwise
+//front faces D3D uses into the counter-clockwise ones they expect.
+fn flip(c: Coord<f32>) -> [f32; 3] {
+    //Adding zero turns -0 into 0
+    [c.x, c.y, -c.z + 0.0]
+}
+/// Wavefront OBJ with per-vertex colours after the position, which Blender reads.
+pub fn write_obj<W: Write>(w: &mut W, mesh: &Mesh) -> io::Result<()> {
+    writeln!(w, "# Exported voxel mesh")?;
+    for v in &mesh.vertices {
+        let [x, y, z] = flip(v.position);
+        let [r, g, b, _] = v.color;
+        writeln!(w, "v {} {} {} {} {} {}", x, y, z, r, g, b)?;
+    }
+    for v in &mesh.vertices {
+        //OBJ texture coordinates start at the bottom
+        writeln!(w, "vt {} {}", v.uv[0], -v.uv[1])?;
+    }
+    for v in &mesh.vertices {
+        let [x, y, z] = flip(v.normal);
+        writeln!(w, "vn {} {} {}", x, y, z)?;
+    }
+    for tri in mesh.indices.to_vec().chunks(3) {
+        //1-based, the same index for position, uv and normal
+        let (a, b, c) = (tri[0] + 1, tri[1] + 1, tri[2] + 1);
+        writeln!(w, "f {0}/{0}/{0} {1}/{1}/{1} {2}/{2}/{2}", a, b, c)?;
+    }
+    Ok(())
+}
+/// Binary glTF 2.0 (.glb): one mesh with positions, normals, uvs and colours in a single buffer.
+pub fn write_glb<W: Write>(w: &mut W, mesh: &Mesh) -> io::Result<()> {
+    let count = mesh.vertices.len();
+    let mut bin = vec![];
+    let (mut min, mut max) = ([f32::MAX; 3], [f32::MIN; 3]);
+    for v in &mesh.vertices {
+        let p = flip(v.position);
+        for a in 0..3 {
+            min[a] = min[a].min(p[a]);
+            max[a] = max[a].max(p[a]);
+            bin.extend_from_slice(&p[a].to_le_bytes());
+        }
+    }
+    let normals = bin.len();
+    for v in &mesh.vertices {
+        for c in &flip(v.normal) {
+            bin.extend_from_slice(&c.to_le_bytes());
+        }
+    }
+    let uvs = bin.len();
+    for v in &mesh.vertices {
+        for c in &v.uv {
+            bin.extend_from_slice(&c.to_le_bytes());
+        }
+    }
+    let colors = bin.len();
+    for v in &mesh.vertices {
+        for c in &v.color {
+            bin.extend_from_slice(&c.to_le_bytes());
+        }
+    }
+    let indices = bin.len();
+    //5123 is UNSIGNED_SHORT and 5125 UNSIGNED_INT
+    let index_type = match &mesh.indices {
+        Indices::U16(list) => {
+            list.iter()
+                .for_each(|i| bin.extend_from_slice(&i.to_le_bytes()));
+            5123
+        }
+        Indices::U32(list) => {
+            list.iter()
+                .for_each(|i| bin.extend_from_slice(&i.to_le_bytes()));
+            5125
+        }
+    };
+    let end = bin.len();
+    while bin.len() % 4 != 0 {
+        bin.push(0);
+    }
+    let view = |offset: usize, len: usize, target: u32| {
+        format!(
+            r#"{{"buffer":0,"byteOffset":{},"byteLength":{},"target":{}}}"#,
+            offset, len, target
+        )
+    };
+    let accessor = |view: usize, component: u32, count: usize, kind: &str| {
+        format!(
+            r#"{{"bufferView":{},"componentType":{},"count":{},"type":"{}"}}"#,
+            view, component, count, kind
+        )
+    };
+    //34962 is ARRAY_BUFFER and 34963 ELEMENT_ARRAY_BUFFER
+    let views = [
+        view(0, normals, 34962),
+        view(normals, uvs - normals, 34962),
+        view(uvs, colors - uvs, 34962),
+        view(colors, indices - colors, 34962),
+        view(indices, end - indices, 34963),
+    ];
+    let positions = format!(
+        r#"{{"bufferView":0,"componentType":5126,"count":{},"type":"VEC3","min":[{},{},{}],"max":[{},{},{}]}}"#,
+        count, min[0], min[1], min[2], max[0], max[1], max[2]
+    );
+    let accessors = [
+        positions,
+        accessor(1, 5126, count, "VEC3"),
+        accessor(2, 5126, count, "VEC2"),
+        accessor(3, 5126, count, "VEC4"),
+        accessor(4, index_type, mesh.indices.len(), "SCALAR"),
+    ];
+    let mut json = format!(
+        concat!(
+            r#"{{"asset":{{"version":"2.0","generator":"voxel game"}},"#,
+            r#""scene":0,"scenes":[{{"nodes":[0]}}],"nodes":[{{"mesh":0}}],"#,
+            r#""meshes":[{{"primitives":[{{"attributes":{{"POSITION":0,"NORMAL":1,"#,
+            r#""TEXCOORD_0":2,"COLOR_0":3}},"indices":4,"material":0}}]}}],"#,
+            r#""materials":[{{"pbrMetallicRoughness":{{"metallicFactor":0}}}}],"#,
+            r#""buffers":[{{"byteLength":{}}}],"bufferViews":[{}],"accessors":[{}]}}"#
+        ),
+        bin.len(),
+        views.join(","),
+        accessors.join(",")
+    );
+    while json.len() % 4 != 0 {
+        json.push(' ');
+    }
+    let total = 12 + 8 + json.len() + 8 + bin.len();
+    w.write_all(b"glTF")?;
+    w.write_all(&2u32.to_le_bytes())?;
+    w.write_all(&(total as u32).to_le_bytes())?;
+    w.write_all(&(json.len() as u32).to_le_bytes())?;
+    w.write_all(b"JSON")?;
+    w.write_all(json.as_bytes())?;
+    w.write_all(&(bin.len() as u32).to_le_bytes())?;
+    w.write_all(b"BIN\0")?;
+    w.write_all(&bin)
+}
+/// Writes `mesh` in the format matching the extension of `path`, `obj` or `glb`.
+pub fn save_mesh(path: &Path, mesh: &Mesh) -> io::Result<()> {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let mut w = BufWriter::new(File::create(path)?);
+    match extension.to_ascii_lowercase().as_str() {
+        "obj" => write_obj(&mut w, mesh)?,
+        "glb" => write_glb(&mut w, mesh)?,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "unknown mesh format, use .obj or .glb",
+            ))
+        }
+    }
+    w.flush()
+}
+/// Writes `world` as a mesh or `.vox` model going by the extension of `output`, for the
+/// command line.
+pub fn export_file(world: &World, output: &Path, meshing: cubes::Meshing) -> io::Result<()> {
+    let cubes = world.load()?;
+    if output
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("vox"))
+    {
+        vox::save(output, &cubes)
+    } else {
+        save_mesh(output, &cubes.to_vertices(meshing, 0, 0))
+    }
+}
+
+#[test]
+fn export_obj() {
+    let cubes = cubes::Cubes::new_list(vec![Coord { x: 0, y: 0, z: 0 }]).unwrap();
+    let mesh = cubes.to_vertices(cubes::Meshing::Naive, 0, 0);
+    let mut buf = vec![];
+    write_obj(&mut buf, &mesh).unwrap();
+    let text = String::from_utf8(buf).unwrap();
+    assert_eq!(text.lines().filter(|l| l.starts_with("v ")).count(), 24);
+    assert_eq!(text.lines().filter(|l| l.starts_with("vn ")).count(), 24);
+    assert_eq!(text.lines().filter(|l| l.starts_with("f ")).count(), 12);
+    assert!(text.contains("f 1/1/1 2/2/2 3/3/3"));
+    assert!(text.lines().any(|l| l == "v 0 0 0 0.5 0.5 0.5"));
+}
+#[test]
+fn export_glb() {
+    let cubes = cubes::Cubes::default_scene();
+    let mesh = cubes.to_vertices(cubes::Meshing::Greedy, 0, 0);
+    let mut buf = vec![];
+    write_glb(&mut buf, &mesh).unwrap();
+    let u32_at = |at| utils::u32_at(&buf, at);
+    assert_eq!(&buf[..4], b"glTF");
+    assert_eq!(u32_at(8) as usize, buf.len());
+    let json_len = u32_at(12) as usize;
+    assert_eq!(json_len % 4, 0);
+    assert_eq!(&buf[16..20], b"JSON");
+    let json = std::str::from_utf8(&buf[20..20 + json_len]).unwrap();
+    assert!(json.contains(&format!(r#""count":{},"type":"VEC3""#, mesh.vertices.len())));
+    let bin_len = u32_at(20 + json_len) as usize;
+    assert_eq!(&buf[24 + json_len..28 + json_len], b"BIN\0");
+    assert_eq!(28 + json_len + bin_len, buf.len());
+    //Positions, normals, uvs, colours then 16-bit indices
+    let expected = mesh.vertices.len() * (12 + 12 + 8 + 16) + mesh.indices.len() * 2;
+    assert_eq!(bin_len, expected.div_ceil(4) * 4);
+}
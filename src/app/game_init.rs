@@ -3,12 +3,21 @@ use super::*;
 impl<'a> App<'a> {
     pub fn init_buffers<'b>(
         device: &ID3D11Device,
-        verticies: &Vec<Coord<f32>>,
-        indicies: &Vec<u16>,
+        mesh: &mesh::Mesh,
     ) -> HResult<[&'b mut ID3D11Buffer; 2]> {
+        //D3D11 can't create empty buffers, so an empty world gets one vertex and index
+        //that are never drawn since its index count stays 0
+        let placeholder;
+        let mesh = if mesh.is_empty() {
+            let vertex = unsafe { MaybeUninit::<mesh::Vertex>::zeroed().assume_init() };
+            placeholder = mesh::Mesh::new(vec![vertex], vec![0]);
+            &placeholder
+        } else {
+            mesh
+        };
         let mut vertex_buffer: *mut ID3D11Buffer = ptr::null_mut();
         let vertex_buffer_desc = D3D11_BUFFER_DESC {
-            ByteWidth: (size_of::<Coord<f32>>() * verticies.len() * 2) as u32,
+            ByteWidth: (size_of::<mesh::Vertex>() * mesh.vertices.len()) as u32,
             Usage: D3D11_USAGE_DEFAULT,
             BindFlags: D3D11_BIND_VERTEX_BUFFER,
             CPUAccessFlags: 0,
@@ -16,7 +25,7 @@ impl<'a> App<'a> {
             StructureByteStride: 0,
         };
         let resource_data = D3D11_SUBRESOURCE_DATA {
-            pSysMem: <*const _>::cast(verticies.as_ptr()),
+            pSysMem: <*const _>::cast(mesh.vertices.as_ptr()),
             SysMemPitch: 0,
             SysMemSlicePitch: 0,
         };
@@ -28,7 +37,7 @@ impl<'a> App<'a> {
         }
         let mut index_buffer: *mut ID3D11Buffer = ptr::null_mut();
         let index_buffer_desc = D3D11_BUFFER_DESC {
-            ByteWidth: (size_of::<u16>() * indicies.len() * 2) as u32,
+            ByteWidth: (mesh.indices.stride() * mesh.indices.len()) as u32,
             Usage: D3D11_USAGE_DEFAULT,
             BindFlags: D3D11_BIND_INDEX_BUFFER,
             CPUAccessFlags: 0,
@@ -36,18 +45,58 @@ impl<'a> App<'a> {
             StructureByteStride: 0,
         };
         let resource_data = D3D11_SUBRESOURCE_DATA {
-            pSysMem: <*const _>::cast(indicies.as_ptr()),
+            pSysMem: <*const _>::cast(mesh.indices.as_ptr()),
             SysMemPitch: 0,
             SysMemSlicePitch: 0,
         };
         let result =
             unsafe { device.CreateBuffer(&index_buffer_desc, &resource_data, &mut index_buffer) };
         if result != 0 {
+            release!(vertex_buffer);
             dbg!();
             return Err(result);
         }
         Ok(unsafe { [&mut *vertex_buffer, &mut *index_buffer] })
     }
+    pub fn index_format(mesh: &mesh::Mesh) -> DXGI_FORMAT {
+        match mesh.indices {
+            mesh::Indices::U16(_) => DXGI_FORMAT_R16_UINT,
+            mesh::Indices::U32(_) => DXGI_FORMAT_R32_UINT,
+        }
+    }
+    /// Replaces the vertex and index buffers, as the mesh can outgrow them or switch index size.
+    pub fn upload_mesh(&mut self, mesh: &mesh::Mesh) -> HResult<()> {
+        let [d_vertex_buffer, d_index_buffer] = Self::init_buffers(self.d_device, mesh)?;
+        release!(self.d_index_buffer);
+        release!(self.d_vertex_buffer);
+        self.d_vertex_buffer = d_vertex_buffer;
+        self.d_index_buffer = d_index_buffer;
+        self.index_format = Self::index_format(mesh);
+        Ok(())
+    }
+    /// Remeshes the chunks edited since the last call and joins every chunk mesh into one.
+    pub fn update_meshes(
+        chunks: &mut chunks::Chunks,
+        meshes: &mut HashMap<Coord<i32>, mesh::Mesh>,
+    ) -> mesh::Mesh {
+        for chunk in chunks.take_dirty() {
+            let mesh = chunks.to_vertices(chunk, cubes::Meshing::Greedy, 0, 0);
+            if mesh.is_empty() {
+                meshes.remove(&chunk);
+            } else {
+                meshes.insert(chunk, mesh);
+            }
+        }
+        mesh::Mesh::merge(meshes.values())
+    }
+    /// Rebuilds the mesh after `chunks` was edited, an empty world draws nothing.
+    pub fn remesh(&mut self) {
+        let mesh = Self::update_meshes(&mut self.chunks, &mut self.chunk_meshes);
+        match self.upload_mesh(&mesh) {
+            Ok(()) => self.index_count = mesh.indices.len() as u32,
+            Err(e) => eprintln!("Failed to upload mesh: {:X}", e),
+        }
+    }
     pub fn init_const_buffers<'b>(device: &ID3D11Device) -> HResult<[&'b mut ID3D11Buffer; 3]> {
         let buffer_desc = D3D11_BUFFER_DESC {
             ByteWidth: size_of::<XMMatrix>() as u32,
@@ -110,20 +159,67 @@ impl<'a> App<'a> {
         Ok(unsafe { (&mut *vertex_shader, &mut *pixel_shader) })
     }
     pub fn init_input_layout<'b>(device: &ID3D11Device) -> HResult<&'b mut ID3D11InputLayout> {
-        let vertex_input_desc = [D3D11_INPUT_ELEMENT_DESC {
-            SemanticName: <*const _>::cast("POSITION\0".as_ptr()),
-            SemanticIndex: 0,
-            Format: DXGI_FORMAT_R32G32B32_FLOAT,
-            InputSlot: 0,
-            AlignedByteOffset: 0,
-            InputSlotClass: D3D11_INPUT_PER_VERTEX_DATA,
-            InstanceDataStepRate: 0,
-        }];
+        let vertex_input_desc = [
+            D3D11_INPUT_ELEMENT_DESC {
+                SemanticName: <*const _>::cast("POSITION\0".as_ptr()),
+                SemanticIndex: 0,
+                Format: DXGI_FORMAT_R32G32B32_FLOAT,
+                InputSlot: 0,
+                AlignedByteOffset: offset_of!(mesh::Vertex, position) as u32,
+                InputSlotClass: D3D11_INPUT_PER_VERTEX_DATA,
+                InstanceDataStepRate: 0,
+            },
+            D3D11_INPUT_ELEMENT_DESC {
+                SemanticName: <*const _>::cast("AO\0".as_ptr()),
+                SemanticIndex: 0,
+                Format: DXGI_FORMAT_R32_FLOAT,
+                InputSlot: 0,
+                AlignedByteOffset: offset_of!(mesh::Vertex, ao) as u32,
+                InputSlotClass: D3D11_INPUT_PER_VERTEX_DATA,
+                InstanceDataStepRate: 0,
+            },
+            D3D11_INPUT_ELEMENT_DESC {
+                SemanticName: <*const _>::cast("NORMAL\0".as_ptr()),
+                SemanticIndex: 0,
+                Format: DXGI_FORMAT_R32G32B32_FLOAT,
+                InputSlot: 0,
+                AlignedByteOffset: offset_of!(mesh::Vertex, normal) as u32,
+                InputSlotClass: D3D11_INPUT_PER_VERTEX_DATA,
+                InstanceDataStepRate: 0,
+            },
+            D3D11_INPUT_ELEMENT_DESC {
+                SemanticName: <*const _>::cast("TEXCOORD\0".as_ptr()),
+                SemanticIndex: 0,
+                Format: DXGI_FORMAT_R32G32_FLOAT,
+                InputSlot: 0,
+                AlignedByteOffset: offset_of!(mesh::Vertex, uv) as u32,
+                InputSlotClass: D3D11_INPUT_PER_VERTEX_DATA,
+                InstanceDataStepRate: 0,
+            },
+            D3D11_INPUT_ELEMENT_DESC {
+                SemanticName: <*const _>::cast("COLOR\0".as_ptr()),
+                SemanticIndex: 0,
+                Format: DXGI_FORMAT_R32G32B32A32_FLOAT,
+                InputSlot: 0,
+                AlignedByteOffset: offset_of!(mesh::Vertex, color) as u32,
+                InputSlotClass: D3D11_INPUT_PER_VERTEX_DATA,
+                InstanceDataStepRate: 0,
+            },
+            D3D11_INPUT_ELEMENT_DESC {
+                SemanticName: <*const _>::cast("BLOCKID\0".as_ptr()),
+                SemanticIndex: 0,
+                Format: DXGI_FORMAT_R32_UINT,
+                InputSlot: 0,
+                AlignedByteOffset: offset_of!(mesh::Vertex, block) as u32,
+                InputSlotClass: D3D11_INPUT_PER_VERTEX_DATA,
+                InstanceDataStepRate: 0,
+            },
+        ];
         let mut input_layout: *mut ID3D11InputLayout = ptr::null_mut();
         let result = unsafe {
             device.CreateInputLayout(
                 vertex_input_desc.as_ptr(),
-                1,
+                vertex_input_desc.len() as u32,
                 <*const _>::cast(VERTEX_SHADER_DATA.as_ptr()),
                 VERTEX_SHADER_DATA.len(),
                 &mut input_layout,
@@ -188,6 +284,106 @@ impl<'a> App<'a> {
             XMMatrix(world_matrix),
         ]
     }
+    /// Builds the starting world and picks where the camera starts.
+    pub fn load_world(world: &World) -> HResult<(chunks::Chunks, Coord<f32>)> {
+        if let World::Generated(seed) = world {
+            //Chunk by chunk, so the region never has to be held twice
+            let generator = terrain::Generator::new(*seed);
+            let (low, _) = chunks::chunk_of(terrain::REGION_START);
+            let (high, _) = chunks::chunk_of(
+                terrain::REGION_START
+                    + Coord {
+                        x: terrain::REGION_LEN.x as i32 - 1,
+                        y: terrain::REGION_LEN.y as i32 - 1,
+                        z: terrain::REGION_LEN.z as i32 - 1,
+                    },
+            );
+            let mut chunks = chunks::Chunks::new();
+            for y in low.y..=high.y {
+                for z in low.z..=high.z {
+                    for x in low.x..=high.x {
+                        generator.fill_chunk(&mut chunks, Coord { x, y, z });
+                    }
+                }
+            }
+            //On the terrain surface at the middle of the region
+            let height = generator.height(0, 0);
+            let spawn = Coord {
+                x: 0.5,
+                y: (height + 1) as f32 + physics::EYE_HEIGHT,
+                z: 0.5,
+            };
+            return Ok((chunks, spawn));
+        }
+        let chunks = match world {
+            World::Saved(path) => {
+                let (chunks, loaded) = save::load(path).map_err(io_error)?;
+                if loaded != *path {
+                    eprintln!(
+                        "Failed to load {}, using backup {}",
+                        path.display(),
+                        loaded.display()
+                    );
+                }
+                chunks
+            }
+            _ => chunks::Chunks::from_cubes(&world.load().map_err(io_error)?),
+        };
+        let spawn = match world {
+            //Looking at the demo cubes from a distance
+            World::Demo => Coord {
+                x: 0.0,
+                y: 0.0,
+                z: -10.0,
+            },
+            _ => Self::spawn_point(&chunks),
+        };
+        Ok((chunks, spawn))
+    }
+    /// Above the highest block of the column at the origin, or above the whole world without one.
+    pub fn spawn_point(chunks: &chunks::Chunks) -> Coord<f32> {
+        let heights: Vec<i32> = chunks
+            .chunk_coords()
+            .map(|c| chunks::chunk_origin(c).y)
+            .collect();
+        let bottom = heights.iter().copied().min().unwrap_or(0);
+        let top = heights.iter().max().map_or(0, |y| y + chunks::CHUNK_SIZE);
+        let ground = (bottom..top)
+            .rev()
+            .find(|&y| chunks.contains(Coord { x: 0, y, z: 0 }))
+            .unwrap_or(top);
+        Coord {
+            x: 0.5,
+            y: (ground + 1) as f32 + physics::EYE_HEIGHT,
+            z: 0.5,
+        }
+    }
+    /// Writes the world next to the save as `.obj` and `.glb`.
+    pub fn export_mesh(&self) {
+        let mesh = self
+            .chunks
+            .to_dense()
+            .to_vertices(cubes::Meshing::Greedy, 0, 0);
+        for extension in &["obj", "glb"] {
+            let path = self.save_path.with_extension(extension);
+            match export::save_mesh(&path, &mesh) {
+                Ok(()) => println!("Exported mesh to {}", path.display()),
+                Err(e) => eprintln!("Failed to export {}: {}", path.display(), e),
+            }
+        }
+    }
+    /// Saves over `save_path`, keeping the previous saves as backups.
+    pub fn save_world(&mut self) -> HResult<()> {
+        //A replay plays out in the recording's world, which doesn't belong at `save_path`
+        if self.replayed {
+            return Ok(());
+        }
+        //Also on failure, so a broken disk isn't retried every frame
+        self.last_save = Instant::now();
+        save::save(&self.save_path, &self.chunks).map_err(io_error)?;
+        self.unsaved = false;
+        Ok(())
+    }
     pub fn load_assets() -> HResult<Vec<Asset>> {
         let file = OpenOptions::new()
             .read(true)
@@ -0,0 +1,216 @@
+use super::*;
+use blocks::BlockId;
+use png::{BitDepth, ColorType, Transformations};
+use std::{fs::File, io::Read};
+
+/// How a heightmap turns into blocks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+    /// Height in blocks of a white pixel over a black one
+    pub scale: f32,
+    /// Height of a black pixel, so the lowest columns still have a floor
+    pub min_height: u32,
+    /// Block and thickness of each layer from the top down
+    pub layers: Vec<(BlockId, u32)>,
+    /// Everything under the layers
+    pub fill: BlockId,
+}
+impl Settings {
+    /// Parses layers written top down like `grass:1,dirt:3,stone`, where the last one has no
+    /// thickness since it fills the rest of the column.
+    pub fn parse_layers(&mut self, text: &str) -> Result<(), String> {
+        let parts: Vec<&str> = text.split(',').map(str::trim).collect();
+        let (last, layers) = parts.split_last().unwrap();
+        let block = |name: &str| blocks::by_name(name).ok_or(format!("Unknown block {}", name));
+        let mut parsed = vec![];
+        for layer in layers {
+            let (name, depth) = match layer.split_once(':') {
+                Some(x) => x,
+                None => return Err(format!("Layer {} needs a thickness", layer)),
+            };
+            let depth = depth
+                .parse()
+                .map_err(|_| format!("Bad thickness in {}", layer))?;
+            parsed.push((block(name)?, depth));
+        }
+        if last.contains(':') {
+            return Err(String::from(
+                "The last layer fills the rest and takes no thickness",
+            ));
+        }
+        self.fill = block(last)?;
+        self.layers = parsed;
+        Ok(())
+    }
+    /// Block `depth` blocks under the top of a column, 0 being the top.
+    fn block_at(&self, depth: u32) -> BlockId {
+        let mut top = 0;
+        for &(id, thickness) in &self.layers {
+            top += thickness;
+            if depth < top {
+                return id;
+            }
+        }
+        self.fill
+    }
+}
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            scale: 32.0,
+            min_height: 1,
+            layers: vec![(blocks::GRASS, 1), (blocks::DIRT, 3)],
+            fill: blocks::STONE,
+        }
+    }
+}
+/// Builds columns from brightness values in [0, 1], `width` per row. Pixel (x, row) becomes the
+/// column at x, z = row with its bottom at y = 0.
+pub fn from_brightness(brightness: &[f32], width: usize, settings: &Settings) -> cubes::Cubes {
+    if width == 0 || brightness.is_empty() {
+        return cubes::Cubes::new();
+    }
+    let depth = brightness.len() / width;
+    let heights: Vec<usize> = brightness
+        .iter()
+        .map(|b| {
+            settings.min_height as usize + (b.clamp(0.0, 1.0) * settings.scale).round() as usize
+        })
+        .collect();
+    let len = Coord {
+        x: width,
+        y: heights.iter().copied().max().unwrap_or(0),
+        z: depth,
+    };
+    if len.y == 0 {
+        return cubes::Cubes::new();
+    }
+    let mut positions = vec![0; len.x * len.y * len.z];
+    for (column, &height) in heights.iter().enumerate().take(width * depth) {
+        for y in 0..height {
+            positions[column + y * len.x * len.z] = settings.block_at((height - 1 - y) as u32);
+        }
+    }
+    cubes::Cubes::from_dense(positions, Coord { x: 0, y: 0, z: 0 }, len)
+}
+/// Reads any PNG as brightness, colours are weighted like a greyscale conversion.
+pub fn read_png<R: Read>(r: R) -> io::Result<(Vec<f32>, usize)> {
+    let mut decoder = Decoder::new(r);
+    //Palettes and bit depths under 8 come out as plain 8-bit channels
+    decoder.set_transformations(Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(utils::png_decoding_error)?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut buf)
+        .map_err(utils::png_decoding_error)?;
+    let channels = match info.color_type {
+        ColorType::Grayscale => 1,
+        ColorType::GrayscaleAlpha => 2,
+        ColorType::Rgb => 3,
+        ColorType::Rgba => 4,
+        ColorType::Indexed => unreachable!(),
+    };
+    let (bytes, max) = match info.bit_depth {
+        BitDepth::Sixteen => (2, u16::MAX as f32),
+        _ => (1, u8::MAX as f32),
+    };
+    let sample = |px: &[u8], c: usize| -> f32 {
+        if bytes == 2 {
+            u16::from_be_bytes([px[c * 2], px[c * 2 + 1]]) as f32 / max
+        } else {
+            px[c] as f32 / max
+        }
+    };
+    let mut brightness = Vec::with_capacity(info.width as usize * info.height as usize);
+    for row in buf[..info.buffer_size()].chunks(info.line_size) {
+        for px in row.chunks(channels * bytes).take(info.width as usize) {
+            brightness.push(if channels >= 3 {
+                0.299 * sample(px, 0) + 0.587 * sample(px, 1) + 0.114 * sample(px, 2)
+            } else {
+                sample(px, 0)
+            });
+        }
+    }
+    Ok((brightness, info.width as usize))
+}
+pub fn load(path: &Path, settings: &Settings) -> io::Result<cubes::Cubes> {
+    let (brightness, width) = read_png(File::open(path)?)?;
+    Ok(from_brightness(&brightness, width, settings))
+}
+
+#[cfg(test)]
+fn encode_png(width: u32, height: u32, color: ColorType, depth: BitDepth, data: &[u8]) -> Vec<u8> {
+    let mut buf = vec![];
+    let mut encoder = png::Encoder::new(&mut buf, width, height);
+    encoder.set_color(color);
+    encoder.set_depth(depth);
+    encoder
+        .write_header()
+        .unwrap()
+        .write_image_data(data)
+        .unwrap();
+    buf
+}
+#[test]
+#[rustfmt::skip::macros(assert_eq)]
+fn heightmap_layers() {
+    let settings = Settings {
+        scale: 4.0,
+        ..Settings::default()
+    };
+    //Black, white and half grey columns in a row
+    let cubes = from_brightness(&[0.0, 1.0, 0.5], 3, &settings);
+    assert_eq!(cubes.size(), Coord { x: 3, y: 5, z: 1 });
+    assert_eq!(cubes.get(Coord { x: 0, y: 0, z: 0 }), blocks::GRASS);
+    assert_eq!(cubes.get(Coord { x: 0, y: 1, z: 0 }), blocks::AIR);
+    assert_eq!(cubes.get(Coord { x: 1, y: 4, z: 0 }), blocks::GRASS);
+    assert_eq!(cubes.get(Coord { x: 1, y: 1, z: 0 }), blocks::DIRT);
+    assert_eq!(cubes.get(Coord { x: 1, y: 0, z: 0 }), blocks::STONE);
+    assert_eq!(cubes.get(Coord { x: 2, y: 2, z: 0 }), blocks::GRASS);
+    assert_eq!(cubes.get(Coord { x: 2, y: 3, z: 0 }), blocks::AIR);
+}
+#[test]
+fn heightmap_parse_layers() {
+    let mut settings = Settings::default();
+    settings.parse_layers("snow:2, stone").unwrap();
+    assert_eq!(settings.layers, vec![(blocks::SNOW, 2)]);
+    assert_eq!(settings.fill, blocks::STONE);
+    settings.parse_layers("sand").unwrap();
+    assert!(settings.layers.is_empty());
+    assert_eq!(settings.fill, blocks::SAND);
+    assert!(settings.parse_layers("grass,stone").is_err());
+    assert!(settings.parse_layers("grass:1,stone:2").is_err());
+    assert!(settings.parse_layers("lava:1,stone").is_err());
+}
+#[test]
+#[rustfmt::skip::macros(assert_eq)]
+fn heightmap_png() {
+    let settings = Settings {
+        scale: 10.0,
+        min_height: 0,
+        ..Settings::default()
+    };
+    let grey = encode_png(
+        2,
+        2,
+        ColorType::Grayscale,
+        BitDepth::Eight,
+        &[0, 255, 51, 102],
+    );
+    let cubes = from_brightness(&read_png(&grey[..]).unwrap().0, 2, &settings);
+    assert_eq!(cubes.size(), Coord { x: 2, y: 10, z: 2 });
+    assert!(cubes.contains(Coord { x: 1, y: 9, z: 0 }));
+    assert!(!cubes.contains(Coord { x: 0, y: 0, z: 0 }));
+    assert!(cubes.contains(Coord { x: 0, y: 1, z: 1 }));
+    assert!(!cubes.contains(Coord { x: 0, y: 2, z: 1 }));
+    assert!(cubes.contains(Coord { x: 1, y: 3, z: 1 }));
+    let rgb = encode_png(
+        1,
+        1,
+        ColorType::Rgb,
+        BitDepth::Sixteen,
+        &[255, 255, 255, 255, 255, 255],
+    );
+    assert_eq!(read_png(&rgb[..]).unwrap(), (vec![1.0], 1));
+    assert!(read_png(&b"not a png"[..]).is_err());
+}
@@ -1,103 +1,82 @@
 use super::*;
+use actions::Action;
 
 impl<'a> App<'a> {
     pub fn hid(&mut self, delta_time: f32) -> i32 {
-        MOUSE.with(|m| {
-            if self.camera.1 == XMVector::set(0.0, 0.0, 1.0, 0.0) {
-                m.set(Position {
-                    x: unsafe { GetSystemMetrics(0) / 2 },
-                    y: unsafe { GetSystemMetrics(1) / 2 },
-                })
+        let paused = match self.state {
+            State::InGame(_) => false,
+            State::Paused(_) => true,
+            _ => return 0,
+        };
+        //Live input is drained even while replaying so it doesn't pile up
+        let delta = MOUSE.with(|m| m.replace(Position { x: 0, y: 0 }));
+        let keys = KEYS.with(|k| k.borrow_mut().take_events());
+        let live = replay::Frame {
+            delta_time,
+            mouse: (delta.x, delta.y),
+            keys,
+            paused,
+        };
+        let mut frame = match self.replay.as_mut().map(Iterator::next) {
+            Some(Some(frame)) => frame,
+            //Hand control back at the end, paused so the player can pick up from here
+            Some(None) => {
+                self.replay = None;
+                return self.pause();
             }
-            let p = m.get();
-            let x_t = unsafe { GetSystemMetrics(0) };
-            let y_t = unsafe { GetSystemMetrics(1) };
-            let (x, y) = (p.x, p.y);
-            let x_angle = (x as f32 / x_t as f32 - 0.5) * PI;
-            let y_angle = ((y as f32 / y_t as f32 - 0.5) * TAU)
-                .clamp(-FRAC_PI_2 + f32::EPSILON, FRAC_PI_2 - f32::EPSILON);
-            let x_rot = XMMatrix(XMMatrixRotationX(y_angle));
-
-            let y_rot = XMMatrix(XMMatrixRotationY(x_angle));
-            let rot = x_rot * y_rot;
-            self.camera.1 = XMVector(XMVector3Transform(XMVectorSet(0.0, 0.0, 1.0, 1.0), rot.0));
-            KEYS.with(|k| {
-                let x = k.borrow();
-                if utils::read_key(VK_ESCAPE as u16, *x) {
-                    unsafe { DestroyWindow(self.h_wnd) };
-                    return -1;
-                }
-                if utils::read_key('W' as u16, *x) {
-                    self.camera.0 += XMVector(XMVector3Transform(
-                        XMVectorSet(0.0, 0.0, delta_time * 3., 0.0),
-                        y_rot.0,
-                    ));
-                }
-                if utils::read_key('S' as u16, *x) {
-                    self.camera.0 += XMVector(XMVector3Transform(
-                        XMVectorSet(0.0, 0.0, -delta_time * 3., 0.0),
-                        y_rot.0,
-                    ));
-                }
-                if utils::read_key('A' as u16, *x) {
-                    self.camera.0 += XMVector(XMVector3Transform(
-                        XMVectorSet(-delta_time * 3., 0.0, 0.0, 0.0),
-                        y_rot.0,
-                    ));
-                }
-                if utils::read_key('D' as u16, *x) {
-                    self.camera.0 += XMVector(XMVector3Transform(
-                        XMVectorSet(delta_time * 3., 0.0, 0.0, 0.0),
-                        y_rot.0,
-                    ));
-                }
-                if utils::read_key('Q' as u16, *x) {
-                    self.camera.0 += XMVector(XMVectorSet(0.0, -delta_time * 3., 0.0, 0.0));
+            None => live,
+        };
+        let actions = self.player.read_input(&frame, &self.bindings);
+        let mut pause = false;
+        //A replay plays out its own pauses, with no menu to answer
+        if self.replay.is_none() {
+            let focused = FOCUSED.with(Cell::get);
+            if paused {
+                //Escape or clicking back into the window resumes, from the next frame
+                if focused
+                    && (actions.pressed(Action::Pause) || actions.pressed(Action::BreakBlock))
+                {
+                    self.resume();
                 }
-                if utils::read_key('E' as u16, *x) {
-                    self.camera.0 += XMVector(XMVectorSet(0.0, delta_time * 3., 0.0, 0.0));
-                }
-                if utils::read_key('L' as u16, *x) {
-                    self.cubes.pop();
-                    let (verticies, indicies) = self.cubes.to_vertices(384, 312);
-                    let (verticies, indicies) = if verticies.is_empty() {
-                        self.cubes = cubes::Cubes::new_list(vec![
-                            Coord { x: -3, y: 0, z: 0 },
-                            Coord { x: -2, y: 0, z: 0 },
-                            Coord { x: -2, y: 1, z: 1 },
-                            Coord { x: -1, y: 0, z: 0 },
-                            Coord { x: -1, y: 0, z: 1 },
-                            Coord { x: -1, y: 1, z: 1 },
-                        ])
-                        .unwrap();
-                        self.cubes.to_vertices(384, 312)
-                    } else {
-                        (verticies, indicies)
-                    };
-                    unsafe {
-                        self.d_device_context.UpdateSubresource(
-                            <*mut _>::cast(self.d_vertex_buffer),
-                            0,
-                            ptr::null(),
-                            <*const _>::cast(verticies.as_ptr()),
-                            0,
-                            0,
-                        )
-                    };
-                    unsafe {
-                        self.d_device_context.UpdateSubresource(
-                            <*mut _>::cast(self.d_index_buffer),
-                            0,
-                            ptr::null(),
-                            <*const _>::cast(indicies.as_ptr()),
-                            0,
-                            0,
-                        )
-                    };
-                    return indicies.len() as i32;
-                }
-                0
-            })
-        })
+            } else if !focused || actions.pressed(Action::Pause) {
+                frame.paused = true;
+                pause = true;
+            }
+        }
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(e) = recorder.push(&frame) {
+                eprintln!("Failed to record input: {}", e);
+                self.recorder = None;
+            }
+        }
+        if pause {
+            return self.pause();
+        }
+        if frame.paused {
+            return 0;
+        }
+        if !self.captured && self.replay.is_none() {
+            self.capture_cursor();
+        }
+        let tick = self.player.tick(&mut self.chunks, &actions, &frame);
+        let (eye, direction) = (self.player.eye, self.player.look.direction());
+        self.camera = (
+            XMVector::set(eye.x, eye.y, eye.z, 0.0),
+            XMVector::set(direction.x, direction.y, direction.z, 0.0),
+        );
+        if tick.save {
+            match self.save_world() {
+                Ok(()) => println!("Saved world to {}", self.save_path.display()),
+                Err(e) => eprintln!("Failed to save world: {:X}", e),
+            }
+        }
+        if tick.export {
+            self.export_mesh();
+        }
+        if tick.edited {
+            self.unsaved = true;
+            self.remesh();
+        }
+        0
     }
 }
@@ -0,0 +1,222 @@
+use super::*;
+
+/// Key transitions as they arrive from `wnd_proc`, latched until the next frame takes them,
+/// so a key pressed and released between two frames still registers.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KeyLatch {
+    down: [u16; 16],
+    pressed: [u16; 16],
+    released: [u16; 16],
+    //The same transitions in order, as `(code, down)`, for recording
+    events: Vec<(u16, bool)>,
+}
+impl KeyLatch {
+    /// Auto-repeated key downs don't count as new presses.
+    pub fn set_key(&mut self, down: bool, code: u16) {
+        if code >= 256 {
+            return;
+        }
+        let (slot, bit) = (code as usize / 16, 1 << (code % 16));
+        let was_down = self.down[slot] & bit != 0;
+        if down && !was_down {
+            self.down[slot] |= bit;
+            self.pressed[slot] |= bit;
+        } else if !down && was_down {
+            self.down[slot] &= !bit;
+            self.released[slot] |= bit;
+        }
+        if down != was_down {
+            self.events.push((code, down));
+        }
+    }
+    /// Lets go of every key that's down, as if they were all released.
+    pub fn release_all(&mut self) {
+        for code in 0..256 {
+            self.set_key(false, code);
+        }
+    }
+    /// Takes the transitions since the last frame without building an `Input`, to hand
+    /// them to another latch.
+    pub fn take_events(&mut self) -> Vec<(u16, bool)> {
+        self.pressed = [0; 16];
+        self.released = [0; 16];
+        std::mem::take(&mut self.events)
+    }
+}
+
+/// Keys and mouse buttons as seen by one frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Input {
+    held: [u16; 16],
+    pressed: [u16; 16],
+    released: [u16; 16],
+    //Seconds each key has been down, 0 when up
+    hold_time: [f32; 256],
+    //hold_time as of the previous frame, to tell when a repeat comes due
+    last_hold_time: [f32; 256],
+}
+impl Input {
+    pub fn new() -> Self {
+        Self {
+            held: [0; 16],
+            pressed: [0; 16],
+            released: [0; 16],
+            hold_time: [0.0; 256],
+            last_hold_time: [0.0; 256],
+        }
+    }
+    /// Takes the transitions latched since the last frame, `delta_time` long.
+    pub fn update(&mut self, latch: &mut KeyLatch, delta_time: f32) {
+        self.held = latch.down;
+        self.pressed = latch.pressed;
+        self.released = latch.released;
+        latch.take_events();
+        self.last_hold_time = self.hold_time;
+        for code in 0..256 {
+            self.hold_time[code] = if !self.held(code as u16) {
+                0.0
+            } else if self.pressed(code as u16) {
+                //Went down during this frame, when exactly isn't known
+                0.0
+            } else {
+                self.hold_time[code] + delta_time
+            };
+        }
+    }
+    /// Down at the end of the frame.
+    pub fn held(&self, code: u16) -> bool {
+        utils::read_key(code, self.held)
+    }
+    /// Went down during the frame, even if it was released again.
+    pub fn pressed(&self, code: u16) -> bool {
+        utils::read_key(code, self.pressed)
+    }
+    /// Went up during the frame.
+    pub fn released(&self, code: u16) -> bool {
+        utils::read_key(code, self.released)
+    }
+    /// How long the key has been held down, in seconds.
+    pub fn hold_time(&self, code: u16) -> f32 {
+        self.hold_time.get(code as usize).copied().unwrap_or(0.0)
+    }
+    /// Like keyboard auto-repeat: true on the press, then every `interval` seconds once
+    /// the key has been held for `delay`.
+    pub fn repeated(&self, code: u16, delay: f32, interval: f32) -> bool {
+        if self.pressed(code) {
+            return true;
+        }
+        if !self.held(code) {
+            return false;
+        }
+        let repeats = |t: f32| {
+            if t < delay {
+                0
+            } else {
+                ((t - delay) / interval) as u32 + 1
+            }
+        };
+        let code = code as usize;
+        code < 256 && repeats(self.hold_time[code]) > repeats(self.last_hold_time[code])
+    }
+}
+impl Default for Input {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn input_edges() {
+    let mut latch = KeyLatch::default();
+    let mut input = Input::new();
+    let w = 'W' as u16;
+    latch.set_key(true, w);
+    input.update(&mut latch, 0.1);
+    assert!(input.pressed(w) && input.held(w) && !input.released(w));
+    //Auto-repeat while held isn't a new press
+    latch.set_key(true, w);
+    input.update(&mut latch, 0.1);
+    assert!(!input.pressed(w) && input.held(w));
+    latch.set_key(false, w);
+    input.update(&mut latch, 0.1);
+    assert!(!input.pressed(w) && !input.held(w) && input.released(w));
+    input.update(&mut latch, 0.1);
+    assert_eq!(input, Input::new());
+}
+#[test]
+fn input_tap_between_frames() {
+    let mut latch = KeyLatch::default();
+    let mut input = Input::new();
+    latch.set_key(true, 0x01);
+    latch.set_key(false, 0x01);
+    input.update(&mut latch, 0.1);
+    assert!(input.pressed(0x01) && input.released(0x01) && !input.held(0x01));
+    input.update(&mut latch, 0.1);
+    assert!(!input.pressed(0x01) && !input.released(0x01));
+    //Out of range codes are ignored
+    latch.set_key(true, 300);
+    assert_eq!(latch, KeyLatch::default());
+}
+#[test]
+fn input_repeated() {
+    let mut latch = KeyLatch::default();
+    let mut input = Input::new();
+    latch.set_key(true, 0x01);
+    let mut fired = vec![];
+    for frame in 0..16 {
+        input.update(&mut latch, 0.125);
+        if input.repeated(0x01, 0.5, 0.25) {
+            fired.push(frame);
+        }
+    }
+    //Pressed on frame 0, held 0.5s by frame 4 then every 2 frames
+    assert_eq!(fired, [0, 4, 6, 8, 10, 12, 14]);
+    latch.set_key(false, 0x01);
+    input.update(&mut latch, 0.125);
+    assert!(!input.repeated(0x01, 0.5, 0.25));
+}
+#[test]
+fn input_events() {
+    let mut latch = KeyLatch::default();
+    latch.set_key(true, 0x20);
+    latch.set_key(true, 0x20);
+    latch.set_key(true, 0x01);
+    latch.set_key(false, 0x20);
+    latch.release_all();
+    assert_eq!(
+        latch.take_events(),
+        vec![(0x20, true), (0x01, true), (0x20, false), (0x01, false)]
+    );
+    assert_eq!(latch, KeyLatch::default());
+    //Replaying the events into another latch gives the same frame
+    let mut live = KeyLatch::default();
+    let mut copy = KeyLatch::default();
+    let (mut a, mut b) = (Input::new(), Input::new());
+    live.set_key(true, 'W' as u16);
+    live.set_key(true, 0x02);
+    live.set_key(false, 0x02);
+    for (code, down) in live.clone().take_events() {
+        copy.set_key(down, code);
+    }
+    a.update(&mut live, 0.1);
+    b.update(&mut copy, 0.1);
+    assert_eq!(a, b);
+}
+#[test]
+fn input_hold_time() {
+    let mut latch = KeyLatch::default();
+    let mut input = Input::new();
+    let l = 'L' as u16;
+    latch.set_key(true, l);
+    input.update(&mut latch, 0.25);
+    assert_eq!(input.hold_time(l), 0.0);
+    input.update(&mut latch, 0.25);
+    input.update(&mut latch, 0.25);
+    assert_eq!(input.hold_time(l), 0.5);
+    latch.set_key(false, l);
+    latch.set_key(true, l);
+    input.update(&mut latch, 0.25);
+    assert!(input.pressed(l) && input.released(l) && input.held(l));
+    assert_eq!(input.hold_time(l), 0.0);
+    assert_eq!(input.hold_time(1000), 0.0);
+}
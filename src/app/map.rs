@@ -0,0 +1,116 @@
+use super::*;
+use png::{BitDepth, ColorType, Encoder};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+/// Top-down RGBA image of `cubes`, one pixel per column with the colour of its highest block.
+/// Rows go from the highest z down, so forward is up. Empty columns stay transparent.
+/// With `shading` higher columns are brighter.
+pub fn render(cubes: &cubes::Cubes, shading: bool) -> (Vec<u8>, usize, usize) {
+    let (start, size) = (cubes.start(), cubes.size());
+    let mut tops = vec![None; size.x * size.z];
+    for z in 0..size.z {
+        for x in 0..size.x {
+            tops[x + z * size.x] = (0..size.y).rev().find_map(|y| {
+                let id = cubes.get(Coord {
+                    x: start.x + x as i32,
+                    y: start.y + y as i32,
+                    z: start.z + z as i32,
+                });
+                if id == blocks::AIR {
+                    None
+                } else {
+                    Some((y, id))
+                }
+            });
+        }
+    }
+    let (low, high) = tops
+        .iter()
+        .flatten()
+        .fold((usize::MAX, 0), |(low, high), &(y, _)| {
+            (low.min(y), high.max(y))
+        });
+    let mut image = vec![0; size.x * size.z * 4];
+    for row in 0..size.z {
+        let z = size.z - 1 - row;
+        for x in 0..size.x {
+            let (y, id) = match tops[x + z * size.x] {
+                Some(top) => top,
+                None => continue,
+            };
+            let color = blocks::get(id).map_or([1.0, 0.0, 1.0, 1.0], |b| b.faces[blocks::UP].color);
+            let light = if shading && high > low {
+                0.5 + 0.5 * (y - low) as f32 / (high - low) as f32
+            } else {
+                1.0
+            };
+            let px = &mut image[(x + row * size.x) * 4..][..4];
+            for c in 0..3 {
+                px[c] = (color[c] * light * 255.0).round() as u8;
+            }
+            px[3] = 255;
+        }
+    }
+    (image, size.x, size.z)
+}
+pub fn write_png<W: Write>(w: W, cubes: &cubes::Cubes, shading: bool) -> io::Result<()> {
+    let (image, width, height) = render(cubes, shading);
+    if width == 0 || height == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the world is empty",
+        ));
+    }
+    let mut encoder = Encoder::new(w, width as u32, height as u32);
+    encoder.set_color(ColorType::Rgba);
+    encoder.set_depth(BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(utils::png_encoding_error)?;
+    writer
+        .write_image_data(&image)
+        .map_err(utils::png_encoding_error)?;
+    writer.finish().map_err(utils::png_encoding_error)
+}
+/// Renders `world` to the PNG at `output`, for the command line.
+pub fn map_file(world: &World, output: &Path, shading: bool) -> io::Result<()> {
+    let cubes = world.load()?;
+    write_png(BufWriter::new(File::create(output)?), &cubes, shading)
+}
+
+#[test]
+fn map_render() {
+    let mut cubes = cubes::Cubes::new();
+    cubes.set(Coord { x: 0, y: 0, z: 0 }, blocks::STONE);
+    cubes.set(Coord { x: 1, y: 0, z: 1 }, blocks::DIRT);
+    cubes.set(Coord { x: 1, y: 2, z: 1 }, blocks::GRASS);
+    let (image, width, height) = render(&cubes, false);
+    assert_eq!((width, height), (2, 2));
+    //Row 0 is z = 1
+    assert_eq!(&image[..4], &[0, 0, 0, 0]);
+    assert_eq!(&image[4..8], &[77, 166, 51, 255]);
+    assert_eq!(&image[8..12], &[128, 128, 128, 255]);
+    let (shaded, _, _) = render(&cubes, true);
+    assert_eq!(&shaded[4..8], &image[4..8]);
+    assert_eq!(&shaded[8..12], &[64, 64, 64, 255]);
+}
+#[test]
+fn map_png() {
+    let cubes = terrain::Generator::new(2).fill_cubes(
+        Coord { x: 0, y: 0, z: 0 },
+        Coord {
+            x: 20,
+            y: 64,
+            z: 10,
+        },
+    );
+    let mut buf = vec![];
+    write_png(&mut buf, &cubes, true).unwrap();
+    let mut reader = Decoder::new(&buf[..]).read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).unwrap();
+    assert_eq!((info.width, info.height), (20, 10));
+    assert_eq!(pixels, render(&cubes, true).0);
+    assert!(write_png(&mut vec![], &cubes::Cubes::new(), false).is_err());
+}
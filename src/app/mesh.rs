@@ -0,0 +1,89 @@
+use super::*;
+
+/// Vertex layout consumed by `VertexShader.hlsl`, faces don't share verticies.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Vertex {
+    pub position: Coord<f32>,
+    /// Ambient occlusion from 0 (fully occluded) to 3 (open)
+    pub ao: f32,
+    pub normal: Coord<f32>,
+    pub uv: [f32; 2],
+    pub color: [f32; 4],
+    /// `BlockId` of the face, also usable as a material index
+    pub block: u32,
+}
+/// Index storage of a `Mesh`, 16-bit whenever every vertex can be addressed with it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Indices {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Indices,
+}
+impl Indices {
+    pub fn len(&self) -> usize {
+        match self {
+            Self::U16(x) => x.len(),
+            Self::U32(x) => x.len(),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Size of one index in bytes.
+    pub fn stride(&self) -> usize {
+        match self {
+            Self::U16(_) => size_of::<u16>(),
+            Self::U32(_) => size_of::<u32>(),
+        }
+    }
+    pub fn as_ptr(&self) -> *const u8 {
+        match self {
+            Self::U16(x) => x.as_ptr().cast(),
+            Self::U32(x) => x.as_ptr().cast(),
+        }
+    }
+    pub fn to_vec(&self) -> Vec<u32> {
+        match self {
+            Self::U16(x) => x.iter().map(|&i| i as u32).collect(),
+            Self::U32(x) => x.clone(),
+        }
+    }
+}
+impl Mesh {
+    pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>) -> Self {
+        let indices = if vertices.len() <= u16::MAX as usize + 1 {
+            Indices::U16(indices.into_iter().map(|i| i as u16).collect())
+        } else {
+            Indices::U32(indices)
+        };
+        Self { vertices, indices }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+    /// Joins `meshes` into one, e.g. the meshes of every chunk into one draw.
+    pub fn merge<'m>(meshes: impl IntoIterator<Item = &'m Mesh>) -> Self {
+        let mut vertices = vec![];
+        let mut indices = vec![];
+        for mesh in meshes {
+            let shift = vertices.len() as u32;
+            indices.extend(mesh.indices.to_vec().into_iter().map(|i| i + shift));
+            vertices.extend_from_slice(&mesh.vertices);
+        }
+        Self::new(vertices, indices)
+    }
+}
+
+impl Default for Mesh {
+    fn default() -> Self {
+        Self {
+            vertices: vec![],
+            indices: Indices::U16(vec![]),
+        }
+    }
+}
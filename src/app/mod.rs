@@ -1,42 +1,86 @@
+//Without the window only the headless tools are built, which leaves most of the game unused
+#![cfg_attr(not(windows), allow(dead_code))]
+mod actions;
+mod biomes;
+mod blocks;
+mod camera;
+mod chunks;
 mod cubes;
+#[cfg(windows)]
 mod directx11_init;
+mod export;
+#[cfg(windows)]
 mod game_init;
+mod heightmap;
+#[cfg(windows)]
 mod hid;
+mod input;
+mod map;
+mod mesh;
+mod physics;
+mod player;
+mod region;
+mod replay;
+#[cfg(windows)]
 mod run;
+mod save;
+mod terrain;
+#[cfg(windows)]
 mod ui;
+mod vox;
+pub use actions::Bindings;
+pub use camera::Settings as CameraSettings;
+pub use cubes::Meshing;
+pub use export::export_file;
+pub use heightmap::Settings as HeightmapSettings;
+pub use map::map_file;
+#[cfg(windows)]
+pub use replay::load as load_replay;
 
 use crate::utils::{self, Coord};
+#[cfg(windows)]
 use directx_math::{
     XMConvertToRadians, XMMatrix, XMMatrixLookAtLH, XMMatrixLookToLH, XMMatrixPerspectiveFovLH,
-    XMMatrixRotationAxis, XMMatrixRotationX, XMMatrixRotationY, XMVector, XMVector3Transform,
-    XMVectorSet,
+    XMMatrixRotationAxis, XMVector,
 };
-use png::{Decoder, DecodingError, OutputInfo};
+use png::Decoder;
+#[cfg(windows)]
+use png::{DecodingError, OutputInfo};
+#[cfg(windows)]
+use replay::Recorder;
+#[cfg(windows)]
 use std::{
     cell::{Cell, RefCell},
-    f32::consts::{FRAC_PI_2, PI, TAU},
+    collections::HashMap,
     fs::OpenOptions,
-    io,
-    mem::{size_of, MaybeUninit},
+    mem::MaybeUninit,
     panic::{catch_unwind, resume_unwind},
-    path::Path,
     ptr,
-    time::{Duration, Instant},
+    time::Instant,
+};
+use std::{
+    f32::consts::{FRAC_PI_2, PI, TAU},
+    io,
+    mem::size_of,
+    path::{Path, PathBuf},
+    time::Duration,
 };
+#[cfg(windows)]
 use winapi::{
     shared::{
         dxgi::{IDXGISwapChain, DXGI_SWAP_CHAIN_DESC, DXGI_SWAP_EFFECT_FLIP_DISCARD},
         dxgiformat::{
-            DXGI_FORMAT_D24_UNORM_S8_UINT, DXGI_FORMAT_R16_UINT, DXGI_FORMAT_R32G32B32_FLOAT,
-            DXGI_FORMAT_R8G8B8A8_UNORM,
+            DXGI_FORMAT, DXGI_FORMAT_D24_UNORM_S8_UINT, DXGI_FORMAT_R16_UINT,
+            DXGI_FORMAT_R32G32B32A32_FLOAT, DXGI_FORMAT_R32G32B32_FLOAT, DXGI_FORMAT_R32G32_FLOAT,
+            DXGI_FORMAT_R32_FLOAT, DXGI_FORMAT_R32_UINT, DXGI_FORMAT_R8G8B8A8_UNORM,
         },
         dxgitype::{
             DXGI_MODE_DESC, DXGI_MODE_SCALING_UNSPECIFIED, DXGI_MODE_SCANLINE_ORDER_UNSPECIFIED,
             DXGI_RATIONAL, DXGI_SAMPLE_DESC, DXGI_USAGE_RENDER_TARGET_OUTPUT,
         },
-        minwindef::{FALSE, LPARAM, LRESULT, TRUE, UINT, WPARAM},
+        minwindef::{FALSE, LOWORD, LPARAM, LRESULT, TRUE, UINT, WPARAM},
         ntdef::HRESULT,
-        windef::{HBRUSH, HWND, RECT},
+        windef::{HBRUSH, HWND, POINT, RECT},
     },
     um::{
         d3d11::{
@@ -60,46 +104,75 @@ use winapi::{
         libloaderapi::GetModuleHandleW,
         unknwnbase::IUnknown,
         winuser::{
-            AdjustWindowRectEx, BeginPaint, CreateWindowExW, DefWindowProcW, DestroyWindow,
+            AdjustWindowRectEx, AppendMenuW, BeginPaint, ClientToScreen, ClipCursor,
+            CreatePopupMenu, CreateWindowExW, DefWindowProcW, DestroyMenu, DestroyWindow,
             DispatchMessageW, EndPaint, GetClientRect, GetRawInputData, GetSystemMetrics,
             LoadCursorW, LoadIconW, PeekMessageW, PostQuitMessage, RegisterClassExW,
-            RegisterRawInputDevices, ShowWindow, TranslateMessage, COLOR_WINDOW, CS_HREDRAW,
-            CS_VREDRAW, CW_USEDEFAULT, HRAWINPUT, IDC_ARROW, IDI_APPLICATION, MOUSE_MOVE_ABSOLUTE,
-            MSG, PAINTSTRUCT, PM_REMOVE, RAWINPUT, RAWINPUTDEVICE, RAWINPUTHEADER, RID_INPUT,
-            RIM_TYPEKEYBOARD, RIM_TYPEMOUSE, RI_KEY_BREAK, SW_SHOW, VK_ESCAPE, WM_CLOSE, WM_CREATE,
-            WM_DESTROY, WM_INPUT, WM_KEYDOWN, WM_KEYUP, WM_PAINT, WM_QUIT, WM_SYSKEYDOWN,
-            WM_SYSKEYUP, WNDCLASSEXW, WS_MAXIMIZE, WS_OVERLAPPEDWINDOW, WS_POPUP,
+            RegisterRawInputDevices, ShowCursor, ShowWindow, TrackPopupMenu, TranslateMessage,
+            COLOR_WINDOW, CS_HREDRAW, CS_VREDRAW, CW_USEDEFAULT, HRAWINPUT, IDC_ARROW,
+            IDI_APPLICATION, MF_GRAYED, MF_SEPARATOR, MF_STRING, MOUSE_MOVE_ABSOLUTE, MSG,
+            PAINTSTRUCT, PM_REMOVE, RAWINPUT, RAWINPUTDEVICE, RAWINPUTHEADER, RID_INPUT,
+            RIM_TYPEKEYBOARD, RIM_TYPEMOUSE, RI_KEY_BREAK, RI_MOUSE_BUTTON_4_DOWN,
+            RI_MOUSE_BUTTON_4_UP, RI_MOUSE_BUTTON_5_DOWN, RI_MOUSE_BUTTON_5_UP,
+            RI_MOUSE_LEFT_BUTTON_DOWN, RI_MOUSE_LEFT_BUTTON_UP, RI_MOUSE_MIDDLE_BUTTON_DOWN,
+            RI_MOUSE_MIDDLE_BUTTON_UP, RI_MOUSE_RIGHT_BUTTON_DOWN, RI_MOUSE_RIGHT_BUTTON_UP,
+            SW_SHOW, TPM_CENTERALIGN, TPM_RETURNCMD, TPM_VCENTERALIGN, VK_LBUTTON, VK_MBUTTON,
+            VK_RBUTTON, VK_XBUTTON1, VK_XBUTTON2, WA_INACTIVE, WM_ACTIVATE, WM_CLOSE, WM_CREATE,
+            WM_DESTROY, WM_INPUT, WM_KEYDOWN, WM_KEYUP, WM_KILLFOCUS, WM_PAINT, WM_QUIT,
+            WM_SYSKEYDOWN, WM_SYSKEYUP, WNDCLASSEXW, WS_MAXIMIZE, WS_OVERLAPPEDWINDOW, WS_POPUP,
         },
     },
     Interface,
 };
 
+#[cfg(windows)]
 type HResult<A> = Result<A, HRESULT>;
 
 const NUM_CONST_BUFFERS: usize = 3;
 const CB_APP: usize = 0;
 const CB_FRAME: usize = 1;
 const CB_OBJECT: usize = 2;
+#[cfg(windows)]
 thread_local! {
-    static KEYS: RefCell<[u16; 16]> = RefCell::new([0; 16]);
+    static KEYS: RefCell<input::KeyLatch> = RefCell::new(input::KeyLatch::default());
     static SYSKEYS: RefCell<[u16; 16]> = RefCell::new([0; 16]);
+    //Mouse motion since the last frame
     static MOUSE: Cell<Position> = Cell::new(Position{x:0,y:0});
+    //Last position from devices reporting absolute coordinates, like tablets and remote desktop
+    static MOUSE_ABSOLUTE: Cell<Option<Position>> = Cell::new(None);
+    //Whether the window has keyboard focus, the game pauses when it doesn't
+    static FOCUSED: Cell<bool> = Cell::new(false);
 }
 const TARGET_FPS: f32 = 60.0;
+//How far away blocks can be targeted
+const REACH: f32 = 6.0;
+pub const DEFAULT_WORLD: &str = "world.vxw";
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(60);
+//Walking below this drops back into flying
+const VOID_DEPTH: f32 = -64.0;
 const MAX_TIME_STEP: Duration =
     Duration::from_nanos(((1.0 / TARGET_FPS) * 60_000_000_000.0) as u64);
+#[cfg(windows)]
 //#[cfg(debug_assertions)]
 const VERTEX_SHADER_DATA: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "\\VertexShader.cso"));
+#[cfg(windows)]
 //#[cfg(debug_assertions)]
 const PIXEL_SHADER_DATA: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "\\PixelShader.cso"));
 
 const MAIN_UI: usize = 0;
+const PAUSE_MENU: u16 = 1;
+//Menu commands
+const RESUME: u16 = 1;
+const SAVE: u16 = 2;
+const QUIT: u16 = 3;
 
+#[cfg(windows)]
 #[derive(Clone, Copy)]
 struct Position {
     x: i32,
     y: i32,
 }
+#[cfg(windows)]
 pub struct App<'a> {
     //General
     window_class_name: String,
@@ -119,52 +192,138 @@ pub struct App<'a> {
     d_input_layout: &'a mut ID3D11InputLayout,
     d_vertex_buffer: &'a mut ID3D11Buffer,
     d_index_buffer: &'a mut ID3D11Buffer,
+    index_format: DXGI_FORMAT,
+    index_count: u32,
     d_vertex_shader: &'a mut ID3D11VertexShader,
     d_pixel_shader: &'a mut ID3D11PixelShader,
     d_constant_buffers: [&'a mut ID3D11Buffer; NUM_CONST_BUFFERS],
     projection_matrix: XMMatrix,
     view_matrix: XMMatrix,
     camera: (XMVector, XMVector),
+    player: player::Player,
+    bindings: Bindings,
+    //Frames left to play instead of live input
+    replay: Option<std::vec::IntoIter<replay::Frame>>,
+    //Started from a replay, which turns saving off until exit
+    replayed: bool,
+    recorder: Option<Recorder>,
+    //Cursor hidden and confined to the window
+    captured: bool,
+    save_path: PathBuf,
+    //Edited since the last save
+    unsaved: bool,
+    last_save: Instant,
     world_matrix: XMMatrix,
     pub t_previous: Instant,
-    cubes: cubes::Cubes,
+    chunks: chunks::Chunks,
+    //Mesh of every chunk with something to draw, only edited chunks are remeshed
+    chunk_meshes: HashMap<Coord<i32>, mesh::Mesh>,
     assets: Vec<Asset>,
     state: State,
     meu_ids: Vec<(u16, Menu)>,
 }
+#[cfg(windows)]
 #[derive(Copy, Clone)]
 pub enum WindowState {
     Windowed(u16, u16),
     Maximized,
     Fullscreen,
 }
+#[cfg(windows)]
 #[derive(Copy, Clone)]
 pub struct Flags {
     pub vsync: bool,
     pub state: self::WindowState,
 }
+/// Where the starting world comes from.
+#[derive(Clone, Debug)]
+pub enum World {
+    /// The handful of cubes from `Cubes::default_scene`
+    Demo,
+    /// Terrain generated from a seed
+    Generated(u64),
+    /// A world written by `save::save`
+    Saved(PathBuf),
+    /// A MagicaVoxel model
+    Vox(PathBuf),
+    /// Columns raised from a greyscale PNG
+    Heightmap(PathBuf, HeightmapSettings),
+}
+impl World {
+    /// A `.vox` model or otherwise a saved world, going by the extension.
+    pub fn from_file(path: &Path) -> Self {
+        if path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("vox"))
+        {
+            Self::Vox(path.into())
+        } else {
+            Self::Saved(path.into())
+        }
+    }
+    /// The world saved at `path`, `None` if nothing was saved there yet.
+    pub fn existing_save(path: &Path) -> Option<Self> {
+        if path.exists() || save::regions_path(path).is_dir() {
+            Some(Self::Saved(path.into()))
+        } else {
+            None
+        }
+    }
+    /// Builds the blocks, doesn't need a window.
+    pub fn load(&self) -> io::Result<cubes::Cubes> {
+        match self {
+            Self::Demo => Ok(cubes::Cubes::default_scene()),
+            Self::Generated(seed) => Ok(terrain::Generator::new(*seed)
+                .fill_cubes(terrain::REGION_START, terrain::REGION_LEN)),
+            Self::Saved(path) => save::load(path).map(|(chunks, _)| chunks.to_dense()),
+            Self::Vox(path) => vox::load(path),
+            Self::Heightmap(path, settings) => heightmap::load(path, settings),
+        }
+    }
+}
+/// Startup settings that don't fit in the `Copy` `Flags`.
+pub struct Options {
+    pub world: World,
+    /// Where the world is saved on exit and with F5
+    pub save_path: PathBuf,
+    /// Which keys trigger each action
+    pub bindings: Bindings,
+    pub camera: CameraSettings,
+    /// Where to record the input stream
+    pub record: Option<PathBuf>,
+    /// Recording to play back instead of live input, starting from its own world
+    pub replay: Option<replay::Recording>,
+}
+#[cfg(windows)]
 pub struct Asset {
     buf: Vec<u8>,
     info: OutputInfo,
 }
+#[cfg(windows)]
 pub enum State {
     Loading,
     MainMenu(u16),
     InGame(u16),
+    /// Simulation stopped and the cursor released, showing a menu from `meu_ids`
+    Paused(u16),
 }
+#[cfg(windows)]
 #[non_exhaustive]
 pub enum Menu {
     Listmeny(ListMenu),
 }
+#[cfg(windows)]
 pub struct ListMenu {
     title: String,
-    buttons: Button,
+    buttons: Vec<Button>,
 }
+#[cfg(windows)]
 pub struct Button {
     active: bool,
     text: String,
     on_click: Option<u16>,
 }
+#[cfg(windows)]
 impl Clone for Asset {
     fn clone(&self) -> Self {
         Self {
@@ -173,8 +332,14 @@ impl Clone for Asset {
         }
     }
 }
+#[cfg(windows)]
 impl<'a> App<'a> {
-    pub fn init(flags: self::Flags, class_name: &str, h_wnd: HWND) -> HResult<Self> {
+    pub fn init(
+        flags: self::Flags,
+        class_name: &str,
+        h_wnd: HWND,
+        options: Options,
+    ) -> HResult<Self> {
         let (d_device, d_device_context, d_swapchain, client_width, client_height) =
             Self::init_device_and_swapchain(h_wnd, flags)?;
         let d_render_target_view = Self::init_rtv(d_swapchain, d_device)?;
@@ -192,18 +357,27 @@ impl<'a> App<'a> {
             MaxDepth: 1.0,
         };
         //Game now
-        let cubes = cubes::Cubes::new_list(vec![
-            Coord { x: -3, y: 0, z: 0 },
-            Coord { x: -2, y: 0, z: 0 },
-            Coord { x: -2, y: 1, z: 1 },
-            Coord { x: -1, y: 0, z: 0 },
-            Coord { x: -1, y: 0, z: 1 },
-            Coord { x: -1, y: 1, z: 1 },
-        ])
-        .unwrap();
-        let (verticies, indicies) = cubes.to_vertices(384, 312);
-        let [d_vertex_buffer, d_index_buffer] =
-            Self::init_buffers(d_device, &verticies, &indicies)?;
+        let (mut chunks, spawn) = match &options.replay {
+            Some(recording) => (
+                chunks::Chunks::from_cubes(&recording.world),
+                recording.spawn,
+            ),
+            None => Self::load_world(&options.world)?,
+        };
+        let recorder = match &options.record {
+            Some(path) => match Recorder::create(path, &chunks.to_dense(), spawn) {
+                Ok(recorder) => Some(recorder),
+                Err(e) => {
+                    eprintln!("Failed to create recording {}: {}", path.display(), e);
+                    return Err(io_error(e));
+                }
+            },
+            None => None,
+        };
+        let mut chunk_meshes = HashMap::new();
+        let mesh = Self::update_meshes(&mut chunks, &mut chunk_meshes);
+        let [d_vertex_buffer, d_index_buffer] = Self::init_buffers(d_device, &mesh)?;
+        let index_format = Self::index_format(&mesh);
         let mut d_constant_buffers = Self::init_const_buffers(d_device)?;
         let (d_vertex_shader, d_pixel_shader) = Self::load_shaders(d_device)?;
         let d_input_layout = Self::init_input_layout(d_device)?;
@@ -214,7 +388,7 @@ impl<'a> App<'a> {
             &mut d_constant_buffers,
         );
         let camera = (
-            XMVector::set(0.0, 0.0, -10.0, 0.0),
+            XMVector::set(spawn.x, spawn.y, spawn.z, 0.0),
             XMVector::set(0.0, 0.0, 1.0, 0.0),
         );
         let assets = Self::load_assets()?;
@@ -237,24 +411,37 @@ impl<'a> App<'a> {
             d_input_layout,
             d_vertex_buffer,
             d_index_buffer,
+            index_format,
+            index_count: mesh.indices.len() as u32,
             d_vertex_shader,
             d_pixel_shader,
             d_constant_buffers,
             projection_matrix,
             view_matrix,
             camera,
+            player: player::Player::new(spawn, options.camera),
+            bindings: options.bindings,
+            replayed: options.replay.is_some(),
+            replay: options.replay.map(|r| r.frames.into_iter()),
+            recorder,
+            captured: false,
+            save_path: options.save_path,
+            //Even a new world is only written once it's edited or saved with F5
+            unsaved: false,
+            last_save: Instant::now(),
             world_matrix,
             t_previous: Instant::now(),
-            cubes,
+            chunks,
+            chunk_meshes,
             assets,
-            state: State::MainMenu(0),
+            state: State::InGame(0),
+            meu_ids: vec![(PAUSE_MENU, Menu::pause())],
         })
     }
     //Main loop
     pub fn run(&mut self) -> HRESULT {
         //Loop
         let mut msg: MaybeUninit<MSG> = MaybeUninit::zeroed();
-        let mut indicies = 0;
         while unsafe { (*msg.as_ptr()).message } != WM_QUIT {
             if unsafe { PeekMessageW(msg.as_mut_ptr(), ptr::null_mut(), 0, 0, PM_REMOVE) } == TRUE {
                 unsafe {
@@ -270,19 +457,30 @@ impl<'a> App<'a> {
                 let min = delta_time.min(MAX_TIME_STEP);
                 delta_time = min;
 
-                let indicies_now = self.hid(delta_time.as_secs_f32());
-                if indicies_now < 0 {
+                if self.hid(delta_time.as_secs_f32()) < 0 {
                     continue;
-                } else if indicies_now > 0 {
-                    indicies = indicies_now;
                 }
                 self.update();
-                self.render(indicies);
+                self.render();
+                if self.unsaved && self.last_save.elapsed() >= AUTOSAVE_INTERVAL {
+                    if let Err(e) = self.save_world() {
+                        eprintln!("Failed to autosave world: {:X}", e);
+                    }
+                }
+            }
+        }
+        if self.unsaved {
+            if let Err(e) = self.save_world() {
+                eprintln!("Failed to save world: {:X}", e);
             }
         }
+        if let Some(Err(e)) = self.recorder.as_mut().map(Recorder::flush) {
+            eprintln!("Failed to record input: {}", e);
+        }
         0
     }
 }
+#[cfg(windows)]
 impl<'a> Drop for App<'a> {
     fn drop(&mut self) {
         release!(self.d_constant_buffers[CB_OBJECT]);
@@ -303,6 +501,7 @@ impl<'a> Drop for App<'a> {
         release!(self.d_device);
     }
 }
+#[cfg(windows)]
 unsafe extern "system" fn wnd_proc(
     h_wnd: HWND,
     msg: UINT,
@@ -355,21 +554,52 @@ unsafe extern "system" fn wnd_proc(
                 let data = read_input(l_param, data_slice, dw_size);
                 if data.header.dwType == RIM_TYPEMOUSE {
                     let mouse = data.data.mouse();
-                    if (mouse.usFlags & MOUSE_MOVE_ABSOLUTE) == MOUSE_MOVE_ABSOLUTE {
-                        MOUSE.with(|m| {
-                            m.set(Position {
-                                x: mouse.lLastX,
-                                y: mouse.lLastY,
-                            })
-                        });
+                    let (dx, dy) = if (mouse.usFlags & MOUSE_MOVE_ABSOLUTE) == MOUSE_MOVE_ABSOLUTE {
+                        //Normalized to 0..65535 across the screen
+                        let p = Position {
+                            x: mouse.lLastX * GetSystemMetrics(0) / 65535,
+                            y: mouse.lLastY * GetSystemMetrics(1) / 65535,
+                        };
+                        match MOUSE_ABSOLUTE.with(|m| m.replace(Some(p))) {
+                            Some(last) => (p.x - last.x, p.y - last.y),
+                            None => (0, 0),
+                        }
                     } else {
-                        MOUSE.with(|m| {
-                            let p = m.get();
-                            m.set(Position {
-                                x: p.x + mouse.lLastX,
-                                y: p.y + mouse.lLastY,
-                            })
-                        });
+                        (mouse.lLastX, mouse.lLastY)
+                    };
+                    MOUSE.with(|m| {
+                        let p = m.get();
+                        m.set(Position {
+                            x: p.x + dx,
+                            y: p.y + dy,
+                        })
+                    });
+                    let buttons = mouse.usButtonFlags;
+                    for &(down, up, key) in &[
+                        (
+                            RI_MOUSE_LEFT_BUTTON_DOWN,
+                            RI_MOUSE_LEFT_BUTTON_UP,
+                            VK_LBUTTON,
+                        ),
+                        (
+                            RI_MOUSE_RIGHT_BUTTON_DOWN,
+                            RI_MOUSE_RIGHT_BUTTON_UP,
+                            VK_RBUTTON,
+                        ),
+                        (
+                            RI_MOUSE_MIDDLE_BUTTON_DOWN,
+                            RI_MOUSE_MIDDLE_BUTTON_UP,
+                            VK_MBUTTON,
+                        ),
+                        (RI_MOUSE_BUTTON_4_DOWN, RI_MOUSE_BUTTON_4_UP, VK_XBUTTON1),
+                        (RI_MOUSE_BUTTON_5_DOWN, RI_MOUSE_BUTTON_5_UP, VK_XBUTTON2),
+                    ] {
+                        if buttons & down != 0 {
+                            set_key(false, true, key as u16);
+                        }
+                        if buttons & up != 0 {
+                            set_key(false, false, key as u16);
+                        }
                     }
                 } else if data.header.dwType == RIM_TYPEKEYBOARD {
                     let keyboard = data.data.keyboard();
@@ -399,6 +629,17 @@ unsafe extern "system" fn wnd_proc(
                 }
                 0
             }
+            //Focus
+            WM_ACTIVATE | WM_KILLFOCUS => {
+                let focused = msg == WM_ACTIVATE && LOWORD(w_param as u32) != WA_INACTIVE;
+                FOCUSED.with(|f| f.set(focused));
+                if !focused {
+                    //Don't hold on to the cursor or keys while another window is in use
+                    ClipCursor(ptr::null());
+                    KEYS.with(|k| k.borrow_mut().release_all());
+                }
+                DefWindowProcW(h_wnd, msg, w_param, l_param)
+            }
             //End
             WM_DESTROY => {
                 PostQuitMessage(0);
@@ -413,6 +654,7 @@ unsafe extern "system" fn wnd_proc(
     })
     .unwrap_or(-2147483648)
 }
+#[cfg(windows)]
 unsafe fn read_input(l_param: LPARAM, data_buf: &mut [u8], mut dw_size: usize) -> &mut RAWINPUT {
     GetRawInputData(
         l_param as HRAWINPUT,
@@ -423,6 +665,7 @@ unsafe fn read_input(l_param: LPARAM, data_buf: &mut [u8], mut dw_size: usize) -
     );
     &mut *<*mut _>::cast(data_buf.as_mut_ptr())
 }
+#[cfg(windows)]
 fn set_key(sys: bool, down: bool, keycode: u16) {
     if keycode < 256 {
         let bit = keycode % 16;
@@ -437,18 +680,12 @@ fn set_key(sys: bool, down: bool, keycode: u16) {
                 }
             })
         } else {
-            KEYS.with(|k| {
-                let mut x = k.borrow_mut();
-                if down {
-                    x[slot as usize] |= 1 << bit;
-                } else {
-                    x[slot as usize] &= !(1 << bit);
-                }
-            })
+            KEYS.with(|k| k.borrow_mut().set_key(down, keycode))
         }
     }
 }
-pub fn io_error(error: io::Error) -> HRESULT {
+/// The HRESULT matching `error`, also used as the exit code of the headless tools.
+pub fn io_error(error: io::Error) -> i32 {
     match error.kind() {
         io::ErrorKind::NotFound => -2147024894,
         io::ErrorKind::PermissionDenied => -2147024891,
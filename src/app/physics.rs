@@ -0,0 +1,445 @@
+use super::*;
+
+/// Player body size, the eye sits `EYE_HEIGHT` above the feet.
+pub const PLAYER_WIDTH: f32 = 0.6;
+pub const PLAYER_HEIGHT: f32 = 1.8;
+pub const EYE_HEIGHT: f32 = 1.6;
+/// Walking mode tuning, in blocks and seconds.
+pub const GRAVITY: f32 = 25.0;
+pub const TERMINAL_VELOCITY: f32 = 50.0;
+pub const JUMP_SPEED: f32 = 8.5;
+//Upward speed kept when jump is let go of early
+pub const JUMP_CUT: f32 = 0.5;
+pub const STEP_HEIGHT: f32 = 1.0;
+//Boxes closer than this count as touching, keeps rounding from pushing them into each other
+const EPSILON: f32 = 1e-3;
+
+/// Axis-aligned box given by its lowest and highest corner.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Coord<f32>,
+    pub max: Coord<f32>,
+}
+/// Result of `sweep`: how far the box could move and which axes got clipped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sweep {
+    pub motion: Coord<f32>,
+    pub blocked: [bool; 3],
+}
+/// Vertical state of a body that walks instead of flying.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Walker {
+    /// Blocks per second, negative while falling.
+    pub velocity_y: f32,
+    pub grounded: bool,
+}
+impl Aabb {
+    pub fn new(min: Coord<f32>, max: Coord<f32>) -> Self {
+        Self { min, max }
+    }
+    /// The player's body for a camera at `eye`.
+    pub fn player(eye: Coord<f32>) -> Self {
+        let half = PLAYER_WIDTH / 2.0;
+        Self {
+            min: Coord {
+                x: eye.x - half,
+                y: eye.y - EYE_HEIGHT,
+                z: eye.z - half,
+            },
+            max: Coord {
+                x: eye.x + half,
+                y: eye.y - EYE_HEIGHT + PLAYER_HEIGHT,
+                z: eye.z + half,
+            },
+        }
+    }
+    /// The unit box of the voxel at `pos`.
+    pub fn block(pos: Coord<i32>) -> Self {
+        let min = Coord {
+            x: pos.x as f32,
+            y: pos.y as f32,
+            z: pos.z as f32,
+        };
+        Self {
+            min,
+            max: min
+                + Coord {
+                    x: 1.0,
+                    y: 1.0,
+                    z: 1.0,
+                },
+        }
+    }
+    pub fn offset(self, by: Coord<f32>) -> Self {
+        Self {
+            min: self.min + by,
+            max: self.max + by,
+        }
+    }
+    /// Whether the boxes overlap, touching faces don't count.
+    pub fn intersects(&self, other: &Self) -> bool {
+        (0..3).all(|a| self.min[a] < other.max[a] - EPSILON && other.min[a] < self.max[a] - EPSILON)
+    }
+    /// Voxel coordinates overlapped on `axis`, ignoring cells only touched at the edge.
+    fn cells(&self, axis: usize) -> std::ops::RangeInclusive<i32> {
+        (self.min[axis] + EPSILON).floor() as i32..=(self.max[axis] - EPSILON).floor() as i32
+    }
+}
+/// Moves `body` by `motion` one axis at a time (y, x, z), clipping each step at the first
+/// solid voxel in `cubes`. Voxels the box already overlaps are ignored so it can't get stuck.
+pub fn sweep(cubes: &chunks::Chunks, body: Aabb, motion: Coord<f32>) -> Sweep {
+    let mut body = body;
+    let mut result = Sweep {
+        motion: Coord {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        },
+        blocked: [false; 3],
+    };
+    for &axis in &[1, 0, 2] {
+        let wanted = motion[axis];
+        if wanted == 0.0 {
+            continue;
+        }
+        let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+        let mut allowed = wanted;
+        //Cells in the order the box runs into them
+        let (first, last, step) = if wanted > 0.0 {
+            let first = (body.max[axis] - EPSILON).ceil() as i32;
+            (first, (body.max[axis] + wanted).floor() as i32, 1)
+        } else {
+            let first = (body.min[axis] + EPSILON).floor() as i32 - 1;
+            (first, (body.min[axis] + wanted).floor() as i32, -1)
+        };
+        let mut cell = first;
+        'scan: while (step > 0 && cell <= last) || (step < 0 && cell >= last) {
+            for i in body.cells(a) {
+                for j in body.cells(b) {
+                    let mut pos = Coord { x: 0, y: 0, z: 0 };
+                    pos[axis] = cell;
+                    pos[a] = i;
+                    pos[b] = j;
+                    if blocks::is_solid(cubes.get(pos)) {
+                        allowed = if step > 0 {
+                            cell as f32 - body.max[axis]
+                        } else {
+                            (cell + 1) as f32 - body.min[axis]
+                        };
+                        result.blocked[axis] = true;
+                        break 'scan;
+                    }
+                }
+            }
+            cell += step;
+        }
+        let mut by = Coord {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        };
+        by[axis] = allowed;
+        body = body.offset(by);
+        result.motion[axis] = allowed;
+    }
+    result
+}
+
+impl Walker {
+    /// Letting go of jump while still rising ends the jump sooner, for lower hops.
+    pub fn cut_jump(&mut self) {
+        if self.velocity_y > 0.0 {
+            self.velocity_y *= JUMP_CUT;
+        }
+    }
+    /// Advances the body by one frame of gravity, jumping and the horizontal part of `motion`.
+    /// Walking into a ledge at most `STEP_HEIGHT` tall while grounded climbs onto it.
+    /// Returns the motion that was actually possible.
+    pub fn step(
+        &mut self,
+        cubes: &chunks::Chunks,
+        body: Aabb,
+        motion: Coord<f32>,
+        jump: bool,
+        delta_time: f32,
+    ) -> Coord<f32> {
+        if jump && self.grounded {
+            self.velocity_y = JUMP_SPEED;
+        }
+        self.velocity_y = (self.velocity_y - GRAVITY * delta_time).max(-TERMINAL_VELOCITY);
+        let flat = Coord {
+            x: motion.x,
+            y: 0.0,
+            z: motion.z,
+        };
+        let fall = Coord {
+            x: 0.0,
+            y: self.velocity_y * delta_time,
+            z: 0.0,
+        };
+        let mut result = sweep(cubes, body, flat + fall);
+        if self.grounded && (result.blocked[0] || result.blocked[2]) {
+            let up = sweep(
+                cubes,
+                body,
+                Coord {
+                    x: 0.0,
+                    y: STEP_HEIGHT,
+                    z: 0.0,
+                },
+            );
+            let raised = body.offset(up.motion);
+            let across = sweep(cubes, raised, flat);
+            let down = sweep(
+                cubes,
+                raised.offset(across.motion),
+                Coord {
+                    x: 0.0,
+                    y: -up.motion.y,
+                    z: 0.0,
+                },
+            );
+            let horizontal = |c: Coord<f32>| c.x * c.x + c.z * c.z;
+            //Only worth it if the step let the body get further
+            if horizontal(across.motion) > horizontal(result.motion) + EPSILON {
+                result = Sweep {
+                    motion: up.motion + across.motion + down.motion,
+                    blocked: [across.blocked[0], down.blocked[1], across.blocked[2]],
+                };
+            }
+        }
+        if result.blocked[1] {
+            self.grounded = self.velocity_y <= 0.0;
+            self.velocity_y = 0.0;
+        } else {
+            self.grounded = false;
+        }
+        result.motion
+    }
+}
+
+#[cfg(test)]
+fn floor_3x3() -> chunks::Chunks {
+    let mut list = vec![];
+    for x in -1..=1 {
+        for z in -1..=1 {
+            list.push(Coord { x, y: 0, z });
+        }
+    }
+    chunks::Chunks::from_cubes(&cubes::Cubes::new_list(list).unwrap())
+}
+#[test]
+#[rustfmt::skip::macros(assert_eq)]
+fn physics_sweep_floor() {
+    let cubes = floor_3x3();
+    let body = Aabb::player(Coord {
+        x: 0.5,
+        y: 1.0 + EYE_HEIGHT + 0.5,
+        z: 0.5,
+    });
+    let s = sweep(
+        &cubes,
+        body,
+        Coord {
+            x: 0.0,
+            y: -2.0,
+            z: 0.0,
+        },
+    );
+    assert!((s.motion.y + 0.5).abs() < 1e-5);
+    assert_eq!(s.blocked, [false, true, false]);
+    //Standing on it, sliding sideways isn't affected by the floor
+    let body = body.offset(s.motion);
+    let s = sweep(
+        &cubes,
+        body,
+        Coord {
+            x: 0.7,
+            y: 0.0,
+            z: -0.3,
+        },
+    );
+    assert_eq!(s.motion, Coord { x: 0.7, y: 0.0, z: -0.3 });
+    assert_eq!(s.blocked, [false; 3]);
+    //Still grounded when pushed down again
+    let s = sweep(
+        &cubes,
+        body,
+        Coord {
+            x: 0.0,
+            y: -0.1,
+            z: 0.0,
+        },
+    );
+    assert!(s.motion.y.abs() < 1e-5);
+    assert!(s.blocked[1]);
+}
+#[test]
+#[rustfmt::skip::macros(assert_eq)]
+fn physics_sweep_wall() {
+    let mut cubes = floor_3x3();
+    cubes.insert(Coord { x: 1, y: 1, z: 0 });
+    cubes.insert(Coord { x: 1, y: 2, z: 0 });
+    let body = Aabb::player(Coord {
+        x: 0.5,
+        y: 1.0 + EYE_HEIGHT,
+        z: 0.5,
+    });
+    //Wall is at x = 1, the body's right side at x = 0.8
+    let s = sweep(
+        &cubes,
+        body,
+        Coord {
+            x: 5.0,
+            y: 0.0,
+            z: 0.1,
+        },
+    );
+    assert!((s.motion.x - 0.2).abs() < 1e-5);
+    assert!((s.motion.z - 0.1).abs() < 1e-5);
+    assert_eq!(s.blocked, [true, false, false]);
+    //Moving away is free
+    let s = sweep(
+        &cubes,
+        body,
+        Coord {
+            x: -0.4,
+            y: 0.0,
+            z: 0.0,
+        },
+    );
+    assert_eq!(s.motion, Coord { x: -0.4, y: 0.0, z: 0.0 });
+    //A fast move still can't tunnel through
+    let s = sweep(
+        &cubes,
+        body.offset(Coord {
+            x: -10.0,
+            y: 0.0,
+            z: 0.0,
+        }),
+        Coord {
+            x: 50.0,
+            y: 0.0,
+            z: 0.0,
+        },
+    );
+    assert!((s.motion.x - 10.2).abs() < 1e-4);
+}
+#[test]
+#[rustfmt::skip::macros(assert_eq)]
+fn physics_sweep_inside() {
+    let cubes = floor_3x3();
+    //Sunk into the floor, the overlapped voxels don't hold the body in place
+    let body = Aabb::player(Coord {
+        x: 0.5,
+        y: 0.5 + EYE_HEIGHT,
+        z: 0.5,
+    });
+    let s = sweep(
+        &cubes,
+        body,
+        Coord {
+            x: 0.0,
+            y: 1.0,
+            z: 0.0,
+        },
+    );
+    assert_eq!(s.motion, Coord { x: 0.0, y: 1.0, z: 0.0 });
+    assert!(body.intersects(&Aabb::block(Coord { x: 0, y: 0, z: 0 })));
+    assert!(!body
+        .offset(s.motion)
+        .intersects(&Aabb::block(Coord { x: 0, y: 0, z: 0 })));
+}
+#[test]
+#[rustfmt::skip::macros(assert_eq)]
+fn physics_walker_fall_and_jump() {
+    let cubes = floor_3x3();
+    let mut walker = Walker::default();
+    let mut body = Aabb::player(Coord {
+        x: 0.5,
+        y: 5.0,
+        z: 0.5,
+    });
+    let still = Coord {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+    for _ in 0..120 {
+        body = body.offset(walker.step(&cubes, body, still, false, 1.0 / 60.0));
+    }
+    assert!(walker.grounded);
+    assert!((body.min.y - 1.0).abs() < 1e-4);
+    body = body.offset(walker.step(&cubes, body, still, true, 1.0 / 60.0));
+    assert!(!walker.grounded);
+    assert!(walker.velocity_y > 0.0);
+    let mut peak = body.min.y;
+    for _ in 0..120 {
+        body = body.offset(walker.step(&cubes, body, still, false, 1.0 / 60.0));
+        peak = peak.max(body.min.y);
+    }
+    assert!(walker.grounded);
+    assert!(peak > 2.2 && peak < 3.0);
+    //Releasing jump right away gives a lower hop
+    body = body.offset(walker.step(&cubes, body, still, true, 1.0 / 60.0));
+    walker.cut_jump();
+    let mut low_peak = body.min.y;
+    for _ in 0..120 {
+        body = body.offset(walker.step(&cubes, body, still, false, 1.0 / 60.0));
+        low_peak = low_peak.max(body.min.y);
+    }
+    assert!(walker.grounded);
+    assert!(low_peak > 1.0 && low_peak < peak - 0.5);
+    walker.cut_jump();
+    assert!(walker.velocity_y <= 0.0);
+    //Walked off the edge
+    let mut walker = Walker::default();
+    let body = Aabb::player(Coord {
+        x: 5.0,
+        y: 5.0,
+        z: 0.5,
+    });
+    for _ in 0..1000 {
+        walker.step(&cubes, body, still, false, 1.0 / 60.0);
+    }
+    assert_eq!(walker.velocity_y, -TERMINAL_VELOCITY);
+}
+#[test]
+fn physics_walker_step_up() {
+    let mut cubes = floor_3x3();
+    cubes.insert(Coord { x: 1, y: 1, z: 0 });
+    let mut walker = Walker::default();
+    let mut body = Aabb::player(Coord {
+        x: 0.5,
+        y: 1.0 + EYE_HEIGHT,
+        z: 0.5,
+    });
+    let step = Coord {
+        x: 0.1,
+        y: 0.0,
+        z: 0.0,
+    };
+    body = body.offset(walker.step(&cubes, body, step, false, 1.0 / 60.0));
+    assert!(walker.grounded);
+    for _ in 0..10 {
+        body = body.offset(walker.step(&cubes, body, step, false, 1.0 / 60.0));
+    }
+    assert!((body.min.y - 2.0).abs() < 1e-4);
+    assert!(body.min.x > 1.0);
+    assert!(walker.grounded);
+    //Two blocks is too high to step onto
+    let mut cubes = floor_3x3();
+    cubes.insert(Coord { x: 1, y: 1, z: 0 });
+    cubes.insert(Coord { x: 1, y: 2, z: 0 });
+    let mut walker = Walker::default();
+    let mut body = Aabb::player(Coord {
+        x: 0.5,
+        y: 1.0 + EYE_HEIGHT,
+        z: 0.5,
+    });
+    for _ in 0..10 {
+        body = body.offset(walker.step(&cubes, body, step, false, 1.0 / 60.0));
+    }
+    assert!((body.min.y - 1.0).abs() < 1e-4);
+    assert!((body.max.x - 1.0).abs() < 1e-4);
+}
@@ -0,0 +1,125 @@
+use super::*;
+use actions::{Action, Actions};
+
+/// What a tick needs the window side to follow up on.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Tick {
+    /// Blocks changed, so the mesh is stale
+    pub edited: bool,
+    pub save: bool,
+    pub export: bool,
+}
+
+/// Everything input changes from frame to frame. Doesn't touch the window or device, so
+/// replays and tests can drive it with `replay::Frame`s.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Player {
+    pub eye: Coord<f32>,
+    pub look: camera::Camera,
+    pub walking: bool,
+    walker: physics::Walker,
+    latch: input::KeyLatch,
+    input: input::Input,
+}
+impl Player {
+    pub fn new(eye: Coord<f32>, camera: CameraSettings) -> Self {
+        Self {
+            eye,
+            look: camera::Camera::new(camera),
+            walking: false,
+            walker: physics::Walker::default(),
+            latch: input::KeyLatch::default(),
+            input: input::Input::new(),
+        }
+    }
+    /// Applies the frame's key transitions, also while paused so nothing is left held.
+    pub fn read_input(&mut self, frame: &replay::Frame, bindings: &Bindings) -> Actions {
+        for &(code, down) in &frame.keys {
+            self.latch.set_key(down, code);
+        }
+        self.input.update(&mut self.latch, frame.delta_time);
+        bindings.resolve(&self.input)
+    }
+    /// Turns, moves and edits `cubes` for one unpaused frame.
+    pub fn tick(
+        &mut self,
+        cubes: &mut chunks::Chunks,
+        actions: &Actions,
+        frame: &replay::Frame,
+    ) -> Tick {
+        let delta_time = frame.delta_time;
+        self.look
+            .turn(frame.mouse.0 as f32, frame.mouse.1 as f32, delta_time);
+        let step = delta_time * 3.;
+        //Horizontal moves follow the yaw only, so looking down doesn't slow walking
+        let (sin, cos) = self.look.yaw.sin_cos();
+        let mut motion = Coord {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        };
+        for &(action, dx, dz) in &[
+            (Action::MoveForward, 0.0, step),
+            (Action::MoveBack, 0.0, -step),
+            (Action::MoveLeft, -step, 0.0),
+            (Action::MoveRight, step, 0.0),
+        ] {
+            if actions.held(action) {
+                motion.x += dx * cos + dz * sin;
+                motion.z += dz * cos - dx * sin;
+            }
+        }
+        if actions.held(Action::MoveDown) {
+            motion.y -= step;
+        }
+        if actions.held(Action::MoveUp) {
+            motion.y += step;
+        }
+        if actions.pressed(Action::ToggleWalk) {
+            self.walking = !self.walking;
+            self.walker = physics::Walker::default();
+        }
+        let body = physics::Aabb::player(self.eye);
+        let clipped = if self.walking {
+            //MoveUp/MoveDown don't apply, gravity and Jump handle the height
+            let jump = actions.held(Action::Jump);
+            if actions.released(Action::Jump) {
+                self.walker.cut_jump();
+            }
+            self.walker.step(cubes, body, motion, jump, delta_time)
+        } else {
+            physics::sweep(cubes, body, motion).motion
+        };
+        self.eye = self.eye + clipped;
+        if self.walking && self.eye.y < VOID_DEPTH {
+            self.walking = false;
+            self.walker = physics::Walker::default();
+        }
+        let mut tick = Tick {
+            edited: false,
+            save: actions.pressed(Action::Save),
+            export: actions.pressed(Action::Export),
+        };
+        if actions.pressed(Action::PopBlock) {
+            cubes.pop();
+            if cubes.is_empty() {
+                cubes.paste(&cubes::Cubes::default_scene());
+            }
+            tick.edited = true;
+        }
+        if let Some(hit) = cubes.raycast(self.eye, self.look.direction(), REACH) {
+            //Holding the button keeps breaking/placing along the way
+            if actions.repeated(Action::BreakBlock) {
+                tick.edited |= cubes.remove(hit.block);
+            }
+            if actions.repeated(Action::PlaceBlock) {
+                let place = hit.block + hit.normal;
+                //Don't bury the player
+                if !physics::Aabb::player(self.eye).intersects(&physics::Aabb::block(place)) {
+                    tick.edited |= cubes.insert(place);
+                }
+            }
+        }
+        tick
+    }
+}
@@ -0,0 +1,351 @@
+use super::*;
+use chunks::{Chunk, Chunks};
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{Read, Seek, SeekFrom, Write},
+};
+
+/// Chunks per axis in one region file.
+pub const REGION_SIZE: i32 = 8;
+const REGION_VOLUME: usize = (REGION_SIZE * REGION_SIZE * REGION_SIZE) as usize;
+pub const MAGIC: [u8; 4] = *b"VXRG";
+pub const VERSION: u16 = 1;
+//Magic, version, then an (offset, length) pair of u32 per chunk
+const HEADER_LEN: u64 = 4 + 2 + REGION_VOLUME as u64 * 8;
+
+/// A grid of chunks in one file. The header holds where each chunk's run-length encoded data
+/// lives, so a single chunk can be read or rewritten without touching the others.
+pub struct Region<F> {
+    file: F,
+    table: Vec<(u32, u32)>,
+    /// End of the last chunk's data
+    end: u64,
+}
+impl<F: Read + Write + Seek> Region<F> {
+    /// Starts an empty region, overwriting whatever `file` held.
+    pub fn create(mut file: F) -> io::Result<Self> {
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&MAGIC)?;
+        file.write_all(&VERSION.to_le_bytes())?;
+        file.write_all(&[0; REGION_VOLUME * 8])?;
+        Ok(Self {
+            file,
+            table: vec![(0, 0); REGION_VOLUME],
+            end: HEADER_LEN,
+        })
+    }
+    pub fn open(mut file: F) -> io::Result<Self> {
+        let mut header = vec![0; HEADER_LEN as usize];
+        file.seek(SeekFrom::Start(0))?;
+        file.read_exact(&mut header)?;
+        if header[..4] != MAGIC {
+            return Err(utils::invalid("not a region file"));
+        }
+        if u16::from_le_bytes([header[4], header[5]]) != VERSION {
+            return Err(utils::invalid("unsupported region version"));
+        }
+        let table: Vec<(u32, u32)> = header[6..]
+            .chunks(8)
+            .map(|e| (utils::u32_at(e, 0), utils::u32_at(e, 4)))
+            .collect();
+        let end = table
+            .iter()
+            .map(|&(offset, len)| offset as u64 + len as u64)
+            .fold(HEADER_LEN, u64::max);
+        Ok(Self { file, table, end })
+    }
+    /// Reads one chunk, `None` if it was never written or is empty.
+    pub fn read_chunk(&mut self, local: Coord<i32>) -> io::Result<Option<Chunk>> {
+        let (offset, len) = self.table[local_index(local)];
+        if len == 0 {
+            return Ok(None);
+        }
+        let mut data = vec![0; len as usize];
+        self.file.seek(SeekFrom::Start(offset as u64))?;
+        self.file.read_exact(&mut data)?;
+        let positions = save::rle_decode(&data, chunks::CHUNK_VOLUME)?;
+        Ok(Some(Chunk::from_positions(positions)))
+    }
+    /// Writes one chunk in place when it still fits, otherwise at the end of the file.
+    pub fn write_chunk(&mut self, local: Coord<i32>, chunk: &Chunk) -> io::Result<()> {
+        let idx = local_index(local);
+        if chunk.is_empty() {
+            return self.set_entry(idx, (0, 0));
+        }
+        let data = save::rle_encode(chunk.positions());
+        let (offset, len) = self.table[idx];
+        let offset = if data.len() as u32 <= len {
+            offset as u64
+        } else {
+            self.end
+        };
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(&data)?;
+        self.end = self.end.max(offset + data.len() as u64);
+        self.set_entry(idx, (offset as u32, data.len() as u32))
+    }
+    fn set_entry(&mut self, idx: usize, entry: (u32, u32)) -> io::Result<()> {
+        self.table[idx] = entry;
+        self.file.seek(SeekFrom::Start(6 + idx as u64 * 8))?;
+        self.file.write_all(&entry.0.to_le_bytes())?;
+        self.file.write_all(&entry.1.to_le_bytes())
+    }
+    /// Bytes in the data area no chunk points at anymore.
+    pub fn wasted(&self) -> u64 {
+        let used: u64 = self.table.iter().map(|&(_, len)| len as u64).sum();
+        self.end - HEADER_LEN - used
+    }
+    /// Slides every chunk down over the gaps left by shrunk or moved chunks.
+    /// Returns the new length of the data, anything in the file past it is garbage.
+    pub fn compact(&mut self) -> io::Result<u64> {
+        let mut order: Vec<usize> = (0..REGION_VOLUME)
+            .filter(|&i| self.table[i].1 > 0)
+            .collect();
+        order.sort_by_key(|&i| self.table[i].0);
+        let mut cursor = HEADER_LEN;
+        for idx in order {
+            let (offset, len) = self.table[idx];
+            if offset as u64 != cursor {
+                let mut data = vec![0; len as usize];
+                self.file.seek(SeekFrom::Start(offset as u64))?;
+                self.file.read_exact(&mut data)?;
+                self.file.seek(SeekFrom::Start(cursor))?;
+                self.file.write_all(&data)?;
+                self.set_entry(idx, (cursor as u32, len))?;
+            }
+            cursor += len as u64;
+        }
+        self.end = cursor;
+        Ok(cursor)
+    }
+    pub fn into_inner(self) -> F {
+        self.file
+    }
+}
+impl Region<File> {
+    /// Compacts and cuts the file down to size.
+    pub fn compact_file(&mut self) -> io::Result<()> {
+        let len = self.compact()?;
+        self.file.set_len(len)
+    }
+}
+/// Region holding `chunk` and the chunk's position inside it.
+pub fn region_of(chunk: Coord<i32>) -> (Coord<i32>, Coord<i32>) {
+    let region = Coord {
+        x: chunk.x.div_euclid(REGION_SIZE),
+        y: chunk.y.div_euclid(REGION_SIZE),
+        z: chunk.z.div_euclid(REGION_SIZE),
+    };
+    let local = Coord {
+        x: chunk.x.rem_euclid(REGION_SIZE),
+        y: chunk.y.rem_euclid(REGION_SIZE),
+        z: chunk.z.rem_euclid(REGION_SIZE),
+    };
+    (region, local)
+}
+fn local_index(local: Coord<i32>) -> usize {
+    assert!((0..3).all(|a| (0..REGION_SIZE).contains(&local[a])));
+    (local.x + local.z * REGION_SIZE + local.y * REGION_SIZE * REGION_SIZE) as usize
+}
+pub fn region_path(dir: &Path, region: Coord<i32>) -> PathBuf {
+    dir.join(format!("r.{}.{}.{}.vxr", region.x, region.y, region.z))
+}
+/// Reverses `region_path`, `None` for any other file.
+fn parse_region_path(path: &Path) -> Option<Coord<i32>> {
+    let name = path.file_name()?.to_str()?;
+    let mut parts = name.strip_prefix("r.")?.strip_suffix(".vxr")?.split('.');
+    let mut next = || parts.next()?.parse().ok();
+    let region = Coord {
+        x: next()?,
+        y: next()?,
+        z: next()?,
+    };
+    match next() {
+        None => Some(region),
+        Some(_) => None,
+    }
+}
+fn open_region(dir: &Path, region: Coord<i32>) -> io::Result<Region<File>> {
+    let path = region_path(dir, region);
+    let file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)?;
+    if file.metadata()?.len() == 0 {
+        Region::create(file)
+    } else {
+        Region::open(file)
+    }
+}
+/// Streams a single chunk out of the region directory `dir`.
+pub fn load_chunk(dir: &Path, chunk: Coord<i32>) -> io::Result<Option<Chunk>> {
+    let (region, local) = region_of(chunk);
+    match File::open(region_path(dir, region)) {
+        Ok(file) => Region::open(file)?.read_chunk(local),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+/// Reads every region file under `dir` back into one world.
+pub fn load_chunks(dir: &Path) -> io::Result<Chunks> {
+    let mut chunks = Chunks::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let region = match parse_region_path(&path) {
+            Some(region) => region,
+            None => continue,
+        };
+        let mut file = Region::open(File::open(&path)?)?;
+        for idx in 0..REGION_VOLUME as i32 {
+            let local = Coord {
+                x: idx % REGION_SIZE,
+                y: idx / (REGION_SIZE * REGION_SIZE),
+                z: idx / REGION_SIZE % REGION_SIZE,
+            };
+            if let Some(chunk) = file.read_chunk(local)? {
+                let origin = Coord {
+                    x: region.x * REGION_SIZE,
+                    y: region.y * REGION_SIZE,
+                    z: region.z * REGION_SIZE,
+                };
+                chunks.insert_chunk(origin + local, chunk);
+            }
+        }
+    }
+    Ok(chunks)
+}
+/// Writes every chunk of `chunks` into region files under `dir`, compacting the ones touched.
+/// Chunks missing from `chunks` are cleared from those regions, regions without any chunk
+/// left are deleted.
+pub fn save_chunks(dir: &Path, chunks: &Chunks) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let regions: HashSet<Coord<i32>> = chunks.chunk_coords().map(|c| region_of(c).0).collect();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if parse_region_path(&path).is_some_and(|r| !regions.contains(&r)) {
+            fs::remove_file(&path)?;
+        }
+    }
+    for region in regions {
+        let mut file = open_region(dir, region)?;
+        let origin = Coord {
+            x: region.x * REGION_SIZE,
+            y: region.y * REGION_SIZE,
+            z: region.z * REGION_SIZE,
+        };
+        for idx in 0..REGION_VOLUME as i32 {
+            let local = Coord {
+                x: idx % REGION_SIZE,
+                y: idx / (REGION_SIZE * REGION_SIZE),
+                z: idx / REGION_SIZE % REGION_SIZE,
+            };
+            match chunks.chunk(origin + local) {
+                Some(chunk) => file.write_chunk(local, chunk)?,
+                None => file.write_chunk(local, &Chunk::new())?,
+            }
+        }
+        file.compact_file()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+fn test_chunk(seed: u8) -> Chunk {
+    let mut chunk = Chunk::new();
+    for i in 0..(seed as usize * 37) {
+        chunk.set(i * 7 % chunks::CHUNK_VOLUME, seed);
+    }
+    chunk
+}
+#[test]
+#[rustfmt::skip::macros(assert_eq)]
+fn region_read_write() {
+    let mut region = Region::create(io::Cursor::new(vec![])).unwrap();
+    let a = Coord { x: 0, y: 0, z: 0 };
+    let b = Coord { x: 7, y: 3, z: 5 };
+    region.write_chunk(a, &test_chunk(1)).unwrap();
+    region.write_chunk(b, &test_chunk(9)).unwrap();
+    assert_eq!(region.read_chunk(a).unwrap(), Some(test_chunk(1)));
+    assert_eq!(region.read_chunk(Coord { x: 1, y: 0, z: 0 }).unwrap(), None);
+    //Reopening only needs the header to find the chunks
+    let mut region = Region::open(region.into_inner()).unwrap();
+    assert_eq!(region.read_chunk(b).unwrap(), Some(test_chunk(9)));
+    assert_eq!(region.read_chunk(a).unwrap(), Some(test_chunk(1)));
+    assert_eq!(region.wasted(), 0);
+    assert!(Region::open(io::Cursor::new(vec![0; HEADER_LEN as usize])).is_err());
+}
+#[test]
+fn region_compact() {
+    let mut region = Region::create(io::Cursor::new(vec![])).unwrap();
+    let a = Coord { x: 1, y: 0, z: 0 };
+    let b = Coord { x: 2, y: 0, z: 0 };
+    region.write_chunk(a, &test_chunk(3)).unwrap();
+    region.write_chunk(b, &test_chunk(4)).unwrap();
+    //Grows, so it has to move to the end and leaves a gap
+    region.write_chunk(a, &test_chunk(20)).unwrap();
+    assert!(region.wasted() > 0);
+    //Shrinks in place
+    region.write_chunk(b, &test_chunk(1)).unwrap();
+    let len = region.compact().unwrap();
+    assert_eq!(region.wasted(), 0);
+    let mut file = region.into_inner();
+    file.get_mut().truncate(len as usize);
+    let mut region = Region::open(file).unwrap();
+    assert_eq!(region.read_chunk(a).unwrap(), Some(test_chunk(20)));
+    assert_eq!(region.read_chunk(b).unwrap(), Some(test_chunk(1)));
+    region.write_chunk(a, &Chunk::new()).unwrap();
+    assert_eq!(region.read_chunk(a).unwrap(), None);
+}
+#[test]
+#[rustfmt::skip::macros(assert_eq)]
+fn region_of_negative() {
+    let (region, local) = region_of(Coord { x: -1, y: 8, z: 15 });
+    assert_eq!(region, Coord { x: -1, y: 1, z: 1 });
+    assert_eq!(local, Coord { x: 7, y: 0, z: 7 });
+    let path = region_path(Path::new("world"), region);
+    assert_eq!(parse_region_path(&path), Some(region));
+    assert_eq!(parse_region_path(Path::new("r.1.2.vxr")), None);
+    assert_eq!(parse_region_path(Path::new("r.1.2.3.4.vxr")), None);
+    assert_eq!(parse_region_path(Path::new("world.vxw")), None);
+}
+#[test]
+fn region_save_chunks() {
+    let dir = std::env::temp_dir().join(format!("region_save_chunks_{}", std::process::id()));
+    let cubes = terrain::Generator::new(11).fill_cubes(
+        Coord {
+            x: -20,
+            y: 0,
+            z: -20,
+        },
+        Coord {
+            x: 40,
+            y: 40,
+            z: 40,
+        },
+    );
+    let chunks = Chunks::from_cubes(&cubes);
+    save_chunks(&dir, &chunks).unwrap();
+    //Saving again after an edit rewrites in place and compacts
+    let mut edited = Chunks::from_cubes(&cubes);
+    edited.set(Coord { x: 0, y: 39, z: 0 }, blocks::GLASS);
+    save_chunks(&dir, &edited).unwrap();
+    save_chunks(&dir, &chunks).unwrap();
+    let mut loaded = Chunks::new();
+    for chunk in chunks.chunk_coords() {
+        loaded.insert_chunk(chunk, load_chunk(&dir, chunk).unwrap().unwrap());
+    }
+    assert_eq!(loaded, chunks);
+    assert_eq!(load_chunks(&dir).unwrap(), chunks);
+    assert_eq!(load_chunk(&dir, Coord { x: 40, y: 0, z: 0 }).unwrap(), None);
+    //Regions left without chunks are removed
+    let far = Coord { x: 500, y: 0, z: 0 };
+    let mut moved = Chunks::new();
+    moved.set(far, blocks::STONE);
+    save_chunks(&dir, &moved).unwrap();
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    assert_eq!(load_chunks(&dir).unwrap(), moved);
+    fs::remove_dir_all(&dir).unwrap();
+}
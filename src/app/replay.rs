@@ -0,0 +1,232 @@
+use super::*;
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+};
+use utils::{invalid, read_array};
+
+/// Recordings start with this, followed by the format version.
+pub const MAGIC: [u8; 4] = *b"VXRP";
+pub const VERSION: u16 = 2;
+
+/// A recording as read back: where it started and everything that happened since.
+#[derive(Debug, PartialEq)]
+pub struct Recording {
+    pub world: cubes::Cubes,
+    pub spawn: Coord<f32>,
+    pub frames: Vec<Frame>,
+}
+/// Everything that drives `Player::tick` for one frame, instead of `WM_INPUT`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Frame {
+    pub delta_time: f32,
+    /// Raw mouse motion
+    pub mouse: (i32, i32),
+    /// Key and mouse button transitions in order, as `(code, down)`
+    pub keys: Vec<(u16, bool)>,
+    /// The game was paused, so only the keys apply
+    pub paused: bool,
+}
+
+/// Layout, all little endian: `delta_time` `f32`, mouse `2 x i32`, paused `u8`,
+/// key count `u32` then a `u16` code and `u8` down for each.
+pub fn write_frame<W: Write>(w: &mut W, frame: &Frame) -> io::Result<()> {
+    let mut buf = Vec::with_capacity(17 + frame.keys.len() * 3);
+    buf.extend_from_slice(&frame.delta_time.to_le_bytes());
+    buf.extend_from_slice(&frame.mouse.0.to_le_bytes());
+    buf.extend_from_slice(&frame.mouse.1.to_le_bytes());
+    buf.push(frame.paused as u8);
+    buf.extend_from_slice(&(frame.keys.len() as u32).to_le_bytes());
+    for &(code, down) in &frame.keys {
+        buf.extend_from_slice(&code.to_le_bytes());
+        buf.push(down as u8);
+    }
+    w.write_all(&buf)
+}
+fn read_bool<R: Read>(r: &mut R) -> io::Result<bool> {
+    match read_array::<_, 1>(r)? {
+        [0] => Ok(false),
+        [1] => Ok(true),
+        _ => Err(invalid("bad flag in recording")),
+    }
+}
+/// `None` at the end of the recording, an error if it ends partway through a frame.
+pub fn read_frame<R: Read>(r: &mut R) -> io::Result<Option<Frame>> {
+    let mut first = [0; 4];
+    if r.read(&mut first[..1])? == 0 {
+        return Ok(None);
+    }
+    r.read_exact(&mut first[1..])?;
+    let delta_time = f32::from_le_bytes(first);
+    let mouse = (
+        i32::from_le_bytes(read_array(r)?),
+        i32::from_le_bytes(read_array(r)?),
+    );
+    let paused = read_bool(r)?;
+    let count = u32::from_le_bytes(read_array(r)?);
+    let mut keys = vec![];
+    for _ in 0..count {
+        keys.push((u16::from_le_bytes(read_array(r)?), read_bool(r)?));
+    }
+    Ok(Some(Frame {
+        delta_time,
+        mouse,
+        keys,
+        paused,
+    }))
+}
+/// Layout: magic, version `u16`, spawn `3 x f32`, the starting world as written by
+/// `save::write_cubes`, then frames until the end of the file.
+pub fn read_recording<R: Read>(r: &mut R) -> io::Result<Recording> {
+    if read_array::<_, 4>(r)? != MAGIC {
+        return Err(invalid("not a recording"));
+    }
+    if u16::from_le_bytes(read_array(r)?) != VERSION {
+        return Err(invalid("unsupported recording version"));
+    }
+    let spawn = Coord {
+        x: f32::from_le_bytes(read_array(r)?),
+        y: f32::from_le_bytes(read_array(r)?),
+        z: f32::from_le_bytes(read_array(r)?),
+    };
+    let world = save::read_cubes(r)?;
+    let mut frames = vec![];
+    while let Some(frame) = read_frame(r)? {
+        frames.push(frame);
+    }
+    Ok(Recording {
+        world,
+        spawn,
+        frames,
+    })
+}
+pub fn load(path: &Path) -> io::Result<Recording> {
+    read_recording(&mut BufReader::new(File::open(path)?))
+}
+
+/// Writes frames as they're played, after the world and spawn they start from.
+/// Replaying also needs the bindings and camera settings the recording was made with.
+pub struct Recorder<W: Write = BufWriter<File>> {
+    w: W,
+}
+impl<W: Write> Recorder<W> {
+    pub fn new(mut w: W, world: &cubes::Cubes, spawn: Coord<f32>) -> io::Result<Self> {
+        w.write_all(&MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+        for v in &[spawn.x, spawn.y, spawn.z] {
+            w.write_all(&v.to_le_bytes())?;
+        }
+        save::write_cubes(&mut w, world)?;
+        Ok(Self { w })
+    }
+    pub fn push(&mut self, frame: &Frame) -> io::Result<()> {
+        write_frame(&mut self.w, frame)
+    }
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+    pub fn into_inner(self) -> W {
+        self.w
+    }
+}
+impl Recorder {
+    pub fn create(path: &Path, world: &cubes::Cubes, spawn: Coord<f32>) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), world, spawn)
+    }
+}
+
+#[cfg(test)]
+fn test_frames() -> Vec<Frame> {
+    let frame = |mouse, keys: &[(char, bool)]| Frame {
+        delta_time: 1.0 / 60.0,
+        mouse,
+        keys: keys.iter().map(|&(c, down)| (c as u16, down)).collect(),
+        paused: false,
+    };
+    let mut frames = vec![frame((40, 0), &[('W', true)])];
+    frames.extend((0..30).map(|i| frame((3, (i % 5) - 1), &[])));
+    frames.push(frame(
+        (0, 200),
+        &[('W', false), ('\u{1}', true), ('\u{1}', false)],
+    ));
+    frames.push(Frame {
+        paused: true,
+        ..frame((500, 500), &[('\u{1b}', true)])
+    });
+    frames.push(frame((0, -100), &[('\u{1b}', false), ('F', true)]));
+    frames.extend((0..40).map(|_| frame((-2, 0), &[('D', true), (' ', true)])));
+    frames.push(frame(
+        (0, 0),
+        &[('\u{2}', true), ('\u{2}', false), ('L', true)],
+    ));
+    frames
+}
+#[cfg(test)]
+fn record(world: &cubes::Cubes, spawn: Coord<f32>, frames: &[Frame]) -> Vec<u8> {
+    let mut recorder = Recorder::new(vec![], world, spawn).unwrap();
+    for frame in frames {
+        recorder.push(frame).unwrap();
+    }
+    recorder.into_inner()
+}
+#[test]
+fn replay_round_trip() {
+    let frames = test_frames();
+    let spawn = Coord {
+        x: 0.5,
+        y: 3.0,
+        z: -2.0,
+    };
+    let buf = record(&cubes::Cubes::default_scene(), spawn, &frames);
+    let recording = read_recording(&mut io::Cursor::new(&buf)).unwrap();
+    assert_eq!(recording.world, cubes::Cubes::default_scene());
+    assert_eq!(recording.spawn, spawn);
+    assert_eq!(recording.frames, frames);
+    assert!(read_recording(&mut io::Cursor::new(&buf[..buf.len() - 1])).is_err());
+    let mut bad_magic = buf.clone();
+    bad_magic[0] = b'X';
+    assert!(read_recording(&mut io::Cursor::new(bad_magic)).is_err());
+    //A recording stopped before the first frame still has its world
+    let header = record(&cubes::Cubes::default_scene(), spawn, &[]);
+    assert_eq!(
+        read_recording(&mut io::Cursor::new(&buf[..header.len()]))
+            .unwrap()
+            .frames,
+        vec![]
+    );
+    assert!(read_recording(&mut io::Cursor::new(&buf[..header.len() - 1])).is_err());
+}
+#[test]
+fn replay_is_deterministic() {
+    let play = |world: &cubes::Cubes, spawn, frames: &[Frame]| {
+        let bindings = Bindings::default();
+        let mut cubes = chunks::Chunks::from_cubes(world);
+        let mut player = player::Player::new(spawn, CameraSettings::default());
+        for frame in frames {
+            let actions = player.read_input(frame, &bindings);
+            if !frame.paused {
+                player.tick(&mut cubes, &actions, frame);
+            }
+        }
+        (player, cubes)
+    };
+    let frames = test_frames();
+    let world = cubes::Cubes::default_scene();
+    let spawn = Coord {
+        x: 0.0,
+        y: 0.0,
+        z: -10.0,
+    };
+    let buf = record(&world, spawn, &frames);
+    let replayed = read_recording(&mut io::Cursor::new(buf)).unwrap();
+    let (live, live_cubes) = play(&world, spawn, &frames);
+    let (replay, replay_cubes) = play(&replayed.world, replayed.spawn, &replayed.frames);
+    assert_eq!(live, replay);
+    assert_eq!(live_cubes, replay_cubes);
+    //Something actually happened
+    assert_ne!(
+        live_cubes,
+        chunks::Chunks::from_cubes(&cubes::Cubes::default_scene())
+    );
+    assert!(live.look.yaw != 0.0 && live.eye.z != -10.0);
+}
@@ -53,14 +53,15 @@ impl<'a> App<'a> {
             }
         }
     }
-    pub fn render(&mut self, indicies: i32) {
+    pub fn render(&mut self) {
         assert!(!(self.d_device_context as *mut ID3D11DeviceContext).is_null());
         assert!(!(self.d_device as *mut ID3D11Device).is_null());
         self.clear([0.3921569, 0.58431375, 0.9294119, 1.0], 1.0, 0);
-        let vertex_stride = size_of::<utils::Coord<f32>>();
+        let vertex_stride = size_of::<mesh::Vertex>();
         let offset = 0;
         let indicies = match self.state {
-            State::InGame(_) => indicies,
+            //The world stays visible behind the pause menu
+            State::InGame(_) | State::Paused(_) => self.index_count,
             _ => 0,
         };
         unsafe {
@@ -74,7 +75,7 @@ impl<'a> App<'a> {
             self.d_device_context
                 .IASetInputLayout(self.d_input_layout as *mut _);
             self.d_device_context
-                .IASetIndexBuffer(self.d_index_buffer, DXGI_FORMAT_R16_UINT, 0);
+                .IASetIndexBuffer(self.d_index_buffer, self.index_format, 0);
             self.d_device_context
                 .IASetPrimitiveTopology(D3D11_PRIMITIVE_TOPOLOGY_TRIANGLELIST);
             self.d_device_context
@@ -95,7 +96,7 @@ impl<'a> App<'a> {
             );
             self.d_device_context
                 .OMSetDepthStencilState(self.d_depth_stencil_state, 1);
-            self.d_device_context.DrawIndexed((indicies) as u32, 0, 0);
+            self.d_device_context.DrawIndexed(indicies, 0, 0);
             self.present();
         }
     }
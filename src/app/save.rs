@@ -0,0 +1,361 @@
+use super::*;
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Write},
+};
+use utils::{invalid, read_u16, read_u32};
+
+/// World files start with this, followed by the format version.
+pub const MAGIC: [u8; 4] = *b"VXWD";
+pub const VERSION: u16 = 2;
+/// How many older saves are kept next to the world file, as `<path>.1` (newest) and up.
+pub const BACKUPS: usize = 3;
+/// Worlds with more chunks than this are saved as region files in `regions_path`, so a save
+/// never has to build the whole world as one block of memory.
+pub const REGION_CHUNKS: usize = 64;
+
+/// Run-length encodes `data` as (count, value) byte pairs.
+pub fn rle_encode(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    let mut iter = data.iter().peekable();
+    while let Some(&value) = iter.next() {
+        let mut count = 1u8;
+        while count < u8::MAX && iter.peek() == Some(&&value) {
+            iter.next();
+            count += 1;
+        }
+        out.push(count);
+        out.push(value);
+    }
+    out
+}
+/// Reverses `rle_encode`, failing unless exactly `len` bytes come out.
+pub fn rle_decode(data: &[u8], len: usize) -> io::Result<Vec<u8>> {
+    if !data.len().is_multiple_of(2) {
+        return Err(invalid("odd run-length data"));
+    }
+    //Checked before allocating, a corrupt size could ask for gigabytes
+    if len > data.len() / 2 * u8::MAX as usize {
+        return Err(invalid("world data is too short"));
+    }
+    let mut out = Vec::with_capacity(len);
+    for pair in data.chunks(2) {
+        if pair[0] == 0 || out.len() + pair[0] as usize > len {
+            return Err(invalid("bad run in world data"));
+        }
+        out.resize(out.len() + pair[0] as usize, pair[1]);
+    }
+    if out.len() != len {
+        return Err(invalid("world data is too short"));
+    }
+    Ok(out)
+}
+/// Layout, all little endian: magic, version `u16`, start `3 x i32`, size `3 x u32`,
+/// data length `u32`, the run-length encoded blocks then a CRC-32 of everything before it.
+/// Version 1 files are the same without the checksum.
+pub fn write_cubes<W: Write>(w: &mut W, cubes: &cubes::Cubes) -> io::Result<()> {
+    let start = cubes.start();
+    let size = cubes.size();
+    let data = rle_encode(cubes.positions());
+    let mut buf = Vec::with_capacity(data.len() + 36);
+    buf.extend_from_slice(&MAGIC);
+    buf.extend_from_slice(&VERSION.to_le_bytes());
+    for v in &[start.x, start.y, start.z] {
+        buf.extend_from_slice(&v.to_le_bytes());
+    }
+    for &v in &[size.x, size.y, size.z] {
+        buf.extend_from_slice(&(v as u32).to_le_bytes());
+    }
+    buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&data);
+    let crc = crc32(&buf);
+    w.write_all(&buf)?;
+    w.write_all(&crc.to_le_bytes())
+}
+pub fn read_cubes<R: Read>(r: &mut R) -> io::Result<cubes::Cubes> {
+    let mut r = Checked { inner: r, crc: !0 };
+    let mut magic = [0; 4];
+    r.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(invalid("not a world file"));
+    }
+    let version = read_u16(&mut r)?;
+    if version == 0 || version > VERSION {
+        return Err(invalid("unsupported world version"));
+    }
+    let start = Coord {
+        x: read_u32(&mut r)? as i32,
+        y: read_u32(&mut r)? as i32,
+        z: read_u32(&mut r)? as i32,
+    };
+    let size = Coord {
+        x: read_u32(&mut r)? as usize,
+        y: read_u32(&mut r)? as usize,
+        z: read_u32(&mut r)? as usize,
+    };
+    let volume = size
+        .x
+        .checked_mul(size.y)
+        .and_then(|v| v.checked_mul(size.z))
+        .ok_or_else(|| invalid("world is too large"))?;
+    let data_len = read_u32(&mut r)? as usize;
+    let mut data = vec![];
+    (&mut r).take(data_len as u64).read_to_end(&mut data)?;
+    if data.len() != data_len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    if version >= 2 {
+        let crc = !r.crc;
+        if read_u32(r.inner)? != crc {
+            return Err(invalid("world checksum mismatch"));
+        }
+    }
+    let positions = rle_decode(&data, volume)?;
+    if volume == 0 {
+        return Ok(cubes::Cubes::new());
+    }
+    Ok(cubes::Cubes::from_dense(positions, start, size))
+}
+/// Standard CRC-32 (IEEE), as used by zip and png.
+pub fn crc32(data: &[u8]) -> u32 {
+    !crc32_update(!0, data)
+}
+fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (!(crc & 1)).wrapping_add(1));
+        }
+    }
+    crc
+}
+/// Keeps a running CRC-32 of everything read through it.
+struct Checked<'r, R> {
+    inner: &'r mut R,
+    crc: u32,
+}
+impl<'r, R: Read> Read for Checked<'r, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.crc = crc32_update(self.crc, &buf[..n]);
+        Ok(n)
+    }
+}
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+pub fn backup_path(path: &Path, n: usize) -> PathBuf {
+    with_suffix(path, &format!(".{}", n))
+}
+/// Directory holding the region files of a large world saved as `path`.
+pub fn regions_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".regions")
+}
+/// Saves `chunks` as region files when there are more than `REGION_CHUNKS` of them, otherwise
+/// as the single file `path`. Whichever layout isn't used is removed, `load` prefers regions.
+pub fn save(path: &Path, chunks: &chunks::Chunks) -> io::Result<()> {
+    let dir = regions_path(path);
+    if chunks.len() > REGION_CHUNKS {
+        return region::save_chunks(&dir, chunks);
+    }
+    save_file(path, &chunks.to_dense())?;
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    Ok(())
+}
+/// Writes to a temporary file first and only then renames it over `path`, so a crash
+/// mid-write never leaves a broken save. The previous save becomes backup 1.
+pub fn save_file(path: &Path, cubes: &cubes::Cubes) -> io::Result<()> {
+    let tmp = with_suffix(path, ".tmp");
+    {
+        let file = File::create(&tmp)?;
+        let mut w = BufWriter::new(&file);
+        write_cubes(&mut w, cubes)?;
+        w.flush()?;
+        drop(w);
+        file.sync_all()?;
+    }
+    if path.exists() {
+        for n in (1..BACKUPS).rev() {
+            let from = backup_path(path, n);
+            if from.exists() {
+                fs::rename(&from, backup_path(path, n + 1))?;
+            }
+        }
+        if BACKUPS > 0 {
+            fs::rename(path, backup_path(path, 1))?;
+        }
+    }
+    fs::rename(&tmp, path)
+}
+fn load_file(path: &Path) -> io::Result<cubes::Cubes> {
+    read_cubes(&mut BufReader::new(File::open(path)?))
+}
+/// Loads the region files of `path` if there are any, otherwise `path` itself, falling back
+/// to the newest backup that passes its checksum.
+/// Also returns the file or directory it ended up reading, so a fallback can be reported.
+pub fn load(path: &Path) -> io::Result<(chunks::Chunks, PathBuf)> {
+    let dir = regions_path(path);
+    if dir.is_dir() {
+        return Ok((region::load_chunks(&dir)?, dir));
+    }
+    let error = match load_file(path) {
+        Ok(cubes) => return Ok((chunks::Chunks::from_cubes(&cubes), path.to_path_buf())),
+        Err(e) => e,
+    };
+    for n in 1..=BACKUPS {
+        let backup = backup_path(path, n);
+        if let Ok(cubes) = load_file(&backup) {
+            return Ok((chunks::Chunks::from_cubes(&cubes), backup));
+        }
+    }
+    Err(error)
+}
+
+#[cfg(test)]
+fn round_trip(cubes: &cubes::Cubes) -> cubes::Cubes {
+    let mut buf = vec![];
+    write_cubes(&mut buf, cubes).unwrap();
+    read_cubes(&mut io::Cursor::new(buf)).unwrap()
+}
+#[test]
+fn save_rle() {
+    let data = [0, 0, 0, 1, 2, 2];
+    assert_eq!(rle_encode(&data), vec![3, 0, 1, 1, 2, 2]);
+    assert_eq!(rle_decode(&rle_encode(&data), 6).unwrap(), data);
+    let long = vec![7; 600];
+    assert_eq!(rle_encode(&long), vec![255, 7, 255, 7, 90, 7]);
+    assert_eq!(rle_decode(&rle_encode(&long), 600).unwrap(), long);
+    assert!(rle_decode(&[3, 0], 2).is_err());
+    assert!(rle_decode(&[3, 0], 4).is_err());
+    assert!(rle_decode(&[0, 0], 0).is_err());
+    assert!(rle_decode(&[255, 1], usize::MAX).is_err());
+}
+#[test]
+fn save_round_trip() {
+    let mut cubes = cubes::Cubes::default_scene();
+    cubes.set(Coord { x: 4, y: -2, z: 7 }, blocks::GLASS);
+    assert_eq!(round_trip(&cubes), cubes);
+    assert_eq!(round_trip(&cubes::Cubes::new()), cubes::Cubes::new());
+    let terrain = terrain::Generator::new(3).fill_cubes(
+        Coord { x: -8, y: 0, z: -8 },
+        Coord {
+            x: 16,
+            y: 48,
+            z: 16,
+        },
+    );
+    let mut buf = vec![];
+    write_cubes(&mut buf, &terrain).unwrap();
+    assert!(buf.len() < terrain.positions().len());
+    assert_eq!(read_cubes(&mut io::Cursor::new(buf)).unwrap(), terrain);
+}
+#[test]
+fn save_rejects_bad_files() {
+    let mut buf = vec![];
+    write_cubes(&mut buf, &cubes::Cubes::default_scene()).unwrap();
+    let mut bad_magic = buf.clone();
+    bad_magic[0] = b'X';
+    assert!(read_cubes(&mut io::Cursor::new(bad_magic)).is_err());
+    let mut bad_version = buf.clone();
+    bad_version[4] = 99;
+    assert!(read_cubes(&mut io::Cursor::new(bad_version)).is_err());
+    //A version 1 header has no checksum, a huge size must fail before anything is allocated
+    let mut huge = buf.clone();
+    huge[4] = 1;
+    huge[18..30].copy_from_slice(&[0xFF; 12]);
+    assert!(read_cubes(&mut io::Cursor::new(huge)).is_err());
+    let mut huge = buf.clone();
+    huge[4] = 1;
+    for axis in 0..3 {
+        huge[18 + axis * 4..22 + axis * 4].copy_from_slice(&2000u32.to_le_bytes());
+    }
+    assert!(read_cubes(&mut io::Cursor::new(huge)).is_err());
+    let truncated = &buf[..buf.len() - 1];
+    assert!(read_cubes(&mut io::Cursor::new(truncated)).is_err());
+    //A flipped bit in the blocks still decodes, only the checksum catches it
+    let mut corrupted = buf.clone();
+    corrupted[buf.len() - 5] ^= 1;
+    let e = read_cubes(&mut io::Cursor::new(corrupted)).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+}
+#[test]
+fn save_crc32() {
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    assert_eq!(crc32(b""), 0);
+}
+#[test]
+fn save_version_1() {
+    let cubes = cubes::Cubes::default_scene();
+    let mut buf = vec![];
+    write_cubes(&mut buf, &cubes).unwrap();
+    buf.truncate(buf.len() - 4);
+    buf[4] = 1;
+    assert_eq!(read_cubes(&mut io::Cursor::new(buf)).unwrap(), cubes);
+}
+#[test]
+fn save_backups() {
+    let dir = std::env::temp_dir().join(format!("save_backups_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("world.vxw");
+    let mut worlds = vec![];
+    for i in 0..5 {
+        let mut cubes = cubes::Cubes::default_scene();
+        cubes.insert(Coord { x: i, y: 5, z: 0 });
+        save_file(&path, &cubes).unwrap();
+        worlds.push(chunks::Chunks::from_cubes(&cubes));
+    }
+    assert!(!with_suffix(&path, ".tmp").exists());
+    assert!(backup_path(&path, BACKUPS).exists());
+    assert!(!backup_path(&path, BACKUPS + 1).exists());
+    let (cubes, loaded) = load(&path).unwrap();
+    assert_eq!(cubes, worlds[4]);
+    assert_eq!(loaded, path);
+    //A save cut off halfway falls back to the newest backup
+    let len = fs::metadata(&path).unwrap().len();
+    let file = fs::OpenOptions::new().write(true).open(&path).unwrap();
+    file.set_len(len / 2).unwrap();
+    drop(file);
+    let (cubes, loaded) = load(&path).unwrap();
+    assert_eq!(cubes, worlds[3]);
+    assert_eq!(loaded, backup_path(&path, 1));
+    fs::write(backup_path(&path, 1), b"garbage").unwrap();
+    let (cubes, loaded) = load(&path).unwrap();
+    assert_eq!(cubes, worlds[2]);
+    assert_eq!(loaded, backup_path(&path, 2));
+    fs::remove_dir_all(&dir).unwrap();
+}
+#[test]
+fn save_large_worlds_as_regions() {
+    let dir = std::env::temp_dir().join(format!("save_regions_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("world.vxw");
+    let small = chunks::Chunks::from_cubes(&cubes::Cubes::default_scene());
+    save(&path, &small).unwrap();
+    assert!(path.exists());
+    assert!(!regions_path(&path).exists());
+    let mut large = chunks::Chunks::new();
+    for x in 0..=REGION_CHUNKS as i32 {
+        large.set(
+            Coord {
+                x: x * 16,
+                y: 0,
+                z: 0,
+            },
+            blocks::STONE,
+        );
+    }
+    save(&path, &large).unwrap();
+    let (chunks, loaded) = load(&path).unwrap();
+    assert_eq!(chunks, large);
+    assert_eq!(loaded, regions_path(&path));
+    //Shrinking back below the limit goes back to one file
+    save(&path, &small).unwrap();
+    assert!(!regions_path(&path).exists());
+    assert_eq!(load(&path).unwrap(), (small, path));
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,358 @@
+use super::*;
+
+/// Size of the region `App` generates around the origin.
+pub const REGION_START: Coord<i32> = Coord {
+    x: -48,
+    y: 0,
+    z: -48,
+};
+pub const REGION_LEN: Coord<usize> = Coord {
+    x: 96,
+    y: 64,
+    z: 96,
+};
+
+//Salts so each noise field is independent while still coming from the same seed
+const HEIGHT_SALT: u64 = 0x68656967;
+const CAVE_SALT: u64 = 0x63617665;
+const TEMPERATURE_SALT: u64 = 0x74656d70;
+const HUMIDITY_SALT: u64 = 0x68756d69;
+const DECORATION_SALT: u64 = 0x64656361;
+
+/// Seeded world generator, the same seed always gives the same blocks.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Generator {
+    pub seed: u64,
+    /// Lowest surface height
+    pub base_height: i32,
+    /// How far above `base_height` hills can reach
+    pub height_range: i32,
+    /// 3D noise above this is carved out, 1 disables caves
+    pub cave_threshold: f32,
+    /// Caves stay this far below the surface
+    pub cave_roof: i32,
+}
+impl Generator {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            base_height: 16,
+            height_range: 32,
+            cave_threshold: 0.72,
+            cave_roof: 4,
+        }
+    }
+    /// Height of the top block at `x`, `z`.
+    pub fn height(&self, x: i32, z: i32) -> i32 {
+        let n = fbm_2d(self.seed ^ HEIGHT_SALT, x as f32 / 64.0, z as f32 / 64.0, 4);
+        //Squaring flattens the lowlands and keeps the hills
+        self.base_height + (n * n * self.height_range as f32) as i32
+    }
+    pub fn is_cave(&self, pos: Coord<i32>, height: i32) -> bool {
+        if pos.y <= 0 || pos.y > height - self.cave_roof {
+            return false;
+        }
+        let p = Coord {
+            x: pos.x as f32 / 16.0,
+            y: pos.y as f32 / 12.0,
+            z: pos.z as f32 / 16.0,
+        };
+        fbm_3d(self.seed ^ CAVE_SALT, p, 2) > self.cave_threshold
+    }
+    pub fn biome(&self, x: i32, z: i32) -> biomes::Biome {
+        let (x, z) = (x as f32 / 256.0, z as f32 / 256.0);
+        biomes::Biome::from_climate(
+            fbm_2d(self.seed ^ TEMPERATURE_SALT, x, z, 2),
+            fbm_2d(self.seed ^ HUMIDITY_SALT, x, z, 2),
+        )
+    }
+    /// Decoration growing on the column at `x`, `z` and its variation.
+    pub fn decoration(&self, x: i32, z: i32) -> Option<(biomes::Decoration, f32)> {
+        let roll = random(self.seed ^ DECORATION_SALT, x, 0, z);
+        let decoration = self.biome(x, z).decoration(roll)?;
+        Some((decoration, random(self.seed ^ DECORATION_SALT, x, 1, z)))
+    }
+    /// Block at `pos` given the surface height and biome of its column.
+    pub fn block(&self, pos: Coord<i32>, height: i32, biome: biomes::Biome) -> blocks::BlockId {
+        if pos.y > height || self.is_cave(pos, height) {
+            blocks::AIR
+        } else if pos.y == height {
+            biome.params().surface
+        } else if pos.y > height - 4 {
+            biome.params().filler
+        } else {
+            blocks::STONE
+        }
+    }
+    /// Generates the box starting at `start`, laid out like `Cubes`.
+    /// Decorations growing just outside of it are included, so adjacent boxes line up.
+    pub fn fill(&self, start: Coord<i32>, len: Coord<usize>) -> Vec<u8> {
+        let mut positions = vec![0; len.x * len.y * len.z];
+        for z in 0..len.z {
+            for x in 0..len.x {
+                let (wx, wz) = (start.x + x as i32, start.z + z as i32);
+                let height = self.height(wx, wz);
+                let biome = self.biome(wx, wz);
+                for y in 0..len.y {
+                    let pos = Coord {
+                        x: wx,
+                        y: start.y + y as i32,
+                        z: wz,
+                    };
+                    positions[x + z * len.x + y * len.x * len.z] = self.block(pos, height, biome);
+                }
+            }
+        }
+        let margin = biomes::MAX_REACH;
+        //Same order for every box, so where decorations overlap the same one wins
+        for wz in start.z - margin..start.z + len.z as i32 + margin {
+            for wx in start.x - margin..start.x + len.x as i32 + margin {
+                let (decoration, variation) = match self.decoration(wx, wz) {
+                    Some(x) => x,
+                    None => continue,
+                };
+                let base = Coord {
+                    x: wx,
+                    y: self.height(wx, wz) + 1,
+                    z: wz,
+                };
+                for (offset, id) in decoration.blocks(variation) {
+                    let pos = base + offset;
+                    let (x, y, z) = (pos.x - start.x, pos.y - start.y, pos.z - start.z);
+                    if x < 0
+                        || y < 0
+                        || z < 0
+                        || x as usize >= len.x
+                        || y as usize >= len.y
+                        || z as usize >= len.z
+                    {
+                        continue;
+                    }
+                    let idx = x as usize + z as usize * len.x + y as usize * len.x * len.z;
+                    //Decorations only grow into air
+                    if positions[idx] == blocks::AIR {
+                        positions[idx] = id;
+                    }
+                }
+            }
+        }
+        positions
+    }
+    pub fn fill_cubes(&self, start: Coord<i32>, len: Coord<usize>) -> cubes::Cubes {
+        cubes::Cubes::from_dense(self.fill(start, len), start, len)
+    }
+    /// Generates one chunk into `chunks`, replacing whatever was there.
+    pub fn fill_chunk(&self, chunks: &mut chunks::Chunks, chunk: Coord<i32>) {
+        let start = chunks::chunk_origin(chunk);
+        let size = chunks::CHUNK_SIZE as usize;
+        let positions = self.fill(
+            start,
+            Coord {
+                x: size,
+                y: size,
+                z: size,
+            },
+        );
+        for (idx, &id) in positions.iter().enumerate() {
+            let pos = Coord {
+                x: start.x + (idx % size) as i32,
+                y: start.y + (idx / (size * size)) as i32,
+                z: start.z + (idx / size % size) as i32,
+            };
+            chunks.set(pos, id);
+        }
+    }
+}
+
+fn hash(seed: u64, x: i32, y: i32, z: i32) -> u64 {
+    let mut h = seed
+        ^ (x as u32 as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (y as u32 as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+        ^ (z as u32 as u64).wrapping_mul(0x1656_67B1_9E37_79F9);
+    //splitmix64 finalizer
+    h = (h ^ (h >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    h ^ (h >> 31)
+}
+/// Uniform value in [0, 1) for a lattice point.
+pub fn random(seed: u64, x: i32, y: i32, z: i32) -> f32 {
+    (hash(seed, x, y, z) >> 40) as f32 / (1u64 << 24) as f32
+}
+fn smooth(t: f32) -> f32 {
+    t * t * (3.0 - 2.0 * t)
+}
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+/// Value noise in [0, 1], smoothly interpolated between lattice points.
+pub fn noise_2d(seed: u64, x: f32, z: f32) -> f32 {
+    let (x0, z0) = (x.floor(), z.floor());
+    let (tx, tz) = (smooth(x - x0), smooth(z - z0));
+    let (x0, z0) = (x0 as i32, z0 as i32);
+    let c = |dx, dz| random(seed, x0 + dx, 0, z0 + dz);
+    lerp(lerp(c(0, 0), c(1, 0), tx), lerp(c(0, 1), c(1, 1), tx), tz)
+}
+pub fn noise_3d(seed: u64, p: Coord<f32>) -> f32 {
+    let (x0, y0, z0) = (p.x.floor(), p.y.floor(), p.z.floor());
+    let (tx, ty, tz) = (smooth(p.x - x0), smooth(p.y - y0), smooth(p.z - z0));
+    let (x0, y0, z0) = (x0 as i32, y0 as i32, z0 as i32);
+    let c = |dx, dy, dz| random(seed, x0 + dx, y0 + dy, z0 + dz);
+    let plane = |dy| {
+        lerp(
+            lerp(c(0, dy, 0), c(1, dy, 0), tx),
+            lerp(c(0, dy, 1), c(1, dy, 1), tx),
+            tz,
+        )
+    };
+    lerp(plane(0), plane(1), ty)
+}
+/// Sums `octaves` layers of noise, each twice the frequency and half the weight, in [0, 1].
+pub fn fbm_2d(seed: u64, x: f32, z: f32, octaves: u32) -> f32 {
+    let (mut sum, mut weight, mut total, mut scale) = (0.0, 1.0, 0.0, 1.0);
+    for octave in 0..octaves {
+        let seed = seed.wrapping_add(octave as u64);
+        sum += noise_2d(seed, x * scale, z * scale) * weight;
+        total += weight;
+        weight *= 0.5;
+        scale *= 2.0;
+    }
+    sum / total
+}
+pub fn fbm_3d(seed: u64, p: Coord<f32>, octaves: u32) -> f32 {
+    let (mut sum, mut weight, mut total, mut scale) = (0.0, 1.0, 0.0, 1.0);
+    for octave in 0..octaves {
+        let seed = seed.wrapping_add(octave as u64);
+        let p = Coord {
+            x: p.x * scale,
+            y: p.y * scale,
+            z: p.z * scale,
+        };
+        sum += noise_3d(seed, p) * weight;
+        total += weight;
+        weight *= 0.5;
+        scale *= 2.0;
+    }
+    sum / total
+}
+
+#[test]
+#[rustfmt::skip::macros(assert_eq)]
+fn terrain_deterministic() {
+    let start = Coord { x: -20, y: 0, z: 5 };
+    let len = Coord {
+        x: 24,
+        y: 48,
+        z: 24,
+    };
+    let a = Generator::new(42).fill_cubes(start, len);
+    let b = Generator::new(42).fill_cubes(start, len);
+    let c = Generator::new(43).fill_cubes(start, len);
+    assert_eq!(a, b);
+    assert_ne!(a, c);
+    //Pinned so a change to the noise that reshuffles existing seeds doesn't go unnoticed
+    let generator = Generator::new(42);
+    assert_eq!(generator.height(0, 0), 17);
+    assert_eq!(generator.height(100, -37), 24);
+    assert_ne!(hash(0, 1, 0, 0), hash(0, 0, 1, 0));
+}
+#[test]
+#[rustfmt::skip::macros(assert_eq)]
+fn terrain_layers() {
+    let generator = Generator::new(7);
+    let cubes = generator.fill_cubes(REGION_START, REGION_LEN);
+    let mut caves = 0;
+    for x in -10..10 {
+        for z in -10..10 {
+            let height = generator.height(x, z);
+            assert!(height >= generator.base_height);
+            assert!(height <= generator.base_height + generator.height_range);
+            let params = generator.biome(x, z).params();
+            assert_eq!(cubes.get(Coord { x, y: height, z }), params.surface);
+            assert_eq!(cubes.get(Coord { x, y: height - 1, z }), params.filler);
+            assert_eq!(cubes.get(Coord { x, y: 0, z }), blocks::STONE);
+            caves += (1..height - 4)
+                .filter(|&y| cubes.get(Coord { x, y, z }) == blocks::AIR)
+                .count();
+        }
+    }
+    assert!(caves > 0);
+    for i in 0..1000 {
+        let n = noise_2d(3, i as f32 * 0.37, -1.3);
+        assert!((0.0..=1.0).contains(&n));
+    }
+}
+#[test]
+#[rustfmt::skip::macros(assert_eq)]
+fn terrain_chunks_match_cubes() {
+    let generator = Generator::new(99);
+    let mut chunks = chunks::Chunks::new();
+    generator.fill_chunk(&mut chunks, Coord { x: -1, y: 1, z: 0 });
+    let cubes = generator.fill_cubes(
+        Coord {
+            x: -16,
+            y: 16,
+            z: 0,
+        },
+        Coord {
+            x: 16,
+            y: 16,
+            z: 16,
+        },
+    );
+    for x in -16..0 {
+        for y in 16..32 {
+            for z in 0..16 {
+                let pos = Coord { x, y, z };
+                assert_eq!(chunks.get(pos), cubes.get(pos));
+            }
+        }
+    }
+}
+#[test]
+#[rustfmt::skip::macros(assert_eq)]
+fn terrain_decorations_cross_boundaries() {
+    let generator = Generator::new(5);
+    let len = Coord {
+        x: 40,
+        y: 64,
+        z: 24,
+    };
+    let whole = generator.fill_cubes(Coord { x: 0, y: 0, z: 0 }, len);
+    let half = Coord {
+        x: 20,
+        y: 64,
+        z: 24,
+    };
+    let left = generator.fill_cubes(Coord { x: 0, y: 0, z: 0 }, half);
+    let right = generator.fill_cubes(Coord { x: 20, y: 0, z: 0 }, half);
+    for x in 0..40 {
+        for y in 0..64 {
+            for z in 0..24 {
+                let pos = Coord { x, y, z };
+                let part = if x < 20 { &left } else { &right };
+                assert_eq!(whole.get(pos), part.get(pos));
+            }
+        }
+    }
+}
+#[test]
+#[rustfmt::skip::macros(assert_eq)]
+fn terrain_biomes() {
+    let generator = Generator::new(1);
+    let mut seen = std::collections::HashSet::new();
+    let mut trees = 0;
+    for x in (-4096..4096).step_by(64) {
+        for z in (-4096..4096).step_by(64) {
+            seen.insert(generator.biome(x, z));
+        }
+    }
+    for x in -200..200 {
+        for z in -200..200 {
+            if let Some((biomes::Decoration::Tree, _)) = generator.decoration(x, z) {
+                trees += 1;
+            }
+        }
+    }
+    assert_eq!(seen.len(), 4);
+    assert!(trees > 0);
+}
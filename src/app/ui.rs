@@ -1,16 +1,140 @@
 use super::*;
 
+impl Menu {
+    /// Shown when the game pauses.
+    pub fn pause() -> Self {
+        let button = |text: &str, on_click| Button {
+            active: true,
+            text: String::from(text),
+            on_click: Some(on_click),
+        };
+        Menu::Listmeny(ListMenu {
+            title: String::from("Paused"),
+            buttons: vec![
+                button("Resume", RESUME),
+                button("Save", SAVE),
+                button("Quit", QUIT),
+            ],
+        })
+    }
+}
 impl<'a> App<'a> {
-    pub fn render_ui(&mut self) {
-        match self.state {
-            State::Loading => unimplemented!(),
-            State::MainMenu(x) => match x {
-                0 => {}
-                _ => {
-                    unimplemented!()
-                }
+    /// Pops the menu up in the middle of the window and waits for a choice.
+    /// Returns the clicked button's `on_click`, `None` if it was dismissed.
+    pub fn show_menu(&self, id: u16) -> Option<u16> {
+        let Menu::Listmeny(list) = &self.meu_ids.iter().find(|(i, _)| *i == id)?.1;
+        unsafe {
+            let h_menu = CreatePopupMenu();
+            if h_menu.is_null() {
+                eprintln!(
+                    "Failed to create menu: {:X}",
+                    utils::win32_to_hresult(GetLastError())
+                );
+                return None;
+            }
+            let title = utils::str_to_c16(&list.title);
+            AppendMenuW(h_menu, MF_STRING | MF_GRAYED, 0, title.as_ptr());
+            AppendMenuW(h_menu, MF_SEPARATOR, 0, ptr::null());
+            for button in &list.buttons {
+                let text = utils::str_to_c16(&button.text);
+                let flags = if button.active && button.on_click.is_some() {
+                    MF_STRING
+                } else {
+                    MF_STRING | MF_GRAYED
+                };
+                let on_click = button.on_click.unwrap_or(0);
+                AppendMenuW(h_menu, flags, on_click as usize, text.as_ptr());
+            }
+            let mut rect: RECT = std::mem::zeroed();
+            GetClientRect(self.h_wnd, &mut rect);
+            let mut center = POINT {
+                x: rect.right / 2,
+                y: rect.bottom / 2,
+            };
+            ClientToScreen(self.h_wnd, &mut center);
+            let choice = TrackPopupMenu(
+                h_menu,
+                TPM_RETURNCMD | TPM_CENTERALIGN | TPM_VCENTERALIGN,
+                center.x,
+                center.y,
+                0,
+                self.h_wnd,
+                ptr::null(),
+            );
+            DestroyMenu(h_menu);
+            match choice {
+                0 => None,
+                x => Some(x as u16),
+            }
+        }
+    }
+    /// Stops the game and hands the cursor back, showing the pause menu if the window
+    /// still has focus. Returns -1 if the player chose to quit.
+    pub fn pause(&mut self) -> i32 {
+        self.release_cursor();
+        self.state = State::Paused(PAUSE_MENU);
+        if !FOCUSED.with(Cell::get) {
+            return 0;
+        }
+        match self.show_menu(PAUSE_MENU) {
+            Some(RESUME) => self.resume(),
+            Some(SAVE) => match self.save_world() {
+                Ok(()) => println!("Saved world to {}", self.save_path.display()),
+                Err(e) => eprintln!("Failed to save world: {:X}", e),
             },
-            State::InGame(u16) => todo!(),
+            Some(QUIT) => {
+                unsafe { DestroyWindow(self.h_wnd) };
+                return -1;
+            }
+            _ => {}
+        }
+        0
+    }
+    /// The cursor is captured again on the next frame.
+    pub fn resume(&mut self) {
+        self.state = State::InGame(0);
+        //Motion from while the menu was open would otherwise jerk the camera on the first frame
+        MOUSE.with(|m| m.set(Position { x: 0, y: 0 }));
+        MOUSE_ABSOLUTE.with(|m| m.set(None));
+    }
+    /// Hides the cursor and keeps it inside the window, mouse-look uses raw deltas anyway.
+    pub fn capture_cursor(&mut self) {
+        unsafe {
+            let mut rect: RECT = std::mem::zeroed();
+            GetClientRect(self.h_wnd, &mut rect);
+            let mut corners = [
+                POINT {
+                    x: rect.left,
+                    y: rect.top,
+                },
+                POINT {
+                    x: rect.right,
+                    y: rect.bottom,
+                },
+            ];
+            for corner in &mut corners {
+                ClientToScreen(self.h_wnd, corner);
+            }
+            let screen = RECT {
+                left: corners[0].x,
+                top: corners[0].y,
+                right: corners[1].x,
+                bottom: corners[1].y,
+            };
+            ClipCursor(&screen);
+            if !self.captured {
+                ShowCursor(FALSE);
+            }
+        }
+        self.captured = true;
+    }
+    pub fn release_cursor(&mut self) {
+        unsafe {
+            ClipCursor(ptr::null());
+            if self.captured {
+                ShowCursor(TRUE);
+            }
         }
+        self.captured = false;
     }
 }
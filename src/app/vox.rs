@@ -0,0 +1,211 @@
+use super::*;
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+};
+use utils::{invalid, u32_at};
+
+//MagicaVoxel files are a header followed by a MAIN chunk holding every other chunk
+const MAGIC: [u8; 4] = *b"VOX ";
+const VERSION: u32 = 150;
+/// MagicaVoxel models can't be larger than this on any axis.
+pub const MAX_SIZE: usize = 256;
+
+/// Colour MagicaVoxel shows for a block, taken from its top face.
+fn block_rgba(id: blocks::BlockId) -> [u8; 4] {
+    let color = blocks::get(id).map_or([1.0, 0.0, 1.0, 1.0], |b| b.faces[blocks::UP].color);
+    let mut rgba = [0; 4];
+    for (c, v) in rgba.iter_mut().zip(color.iter()) {
+        *c = (v * 255.0).round() as u8;
+    }
+    rgba
+}
+/// Block whose colour is closest to `rgba`.
+pub fn nearest_block(rgba: [u8; 4]) -> blocks::BlockId {
+    (1..blocks::REGISTRY.len() as blocks::BlockId)
+        .min_by_key(|&id| {
+            let other = block_rgba(id);
+            rgba.iter()
+                .zip(other.iter())
+                .map(|(&a, &b)| (a as i32 - b as i32).pow(2))
+                .sum::<i32>()
+        })
+        .unwrap()
+}
+/// Reads the first model of a .vox file. Palette colours are mapped to the closest block type;
+/// files without a palette use the colour index as the block ID.
+/// MagicaVoxel is z-up, so its y and z are swapped.
+pub fn read_vox<R: Read>(r: &mut R) -> io::Result<cubes::Cubes> {
+    let mut header = [0; 8];
+    r.read_exact(&mut header)?;
+    if header[..4] != MAGIC {
+        return Err(invalid("not a .vox file"));
+    }
+    let mut data = vec![];
+    r.read_to_end(&mut data)?;
+    let mut size = None;
+    let mut voxels: Option<&[u8]> = None;
+    let mut palette = None;
+    let mut rest = &data[..];
+    while rest.len() >= 12 {
+        let id = &rest[..4];
+        let content_len = u32_at(rest, 4) as usize;
+        let children_len = u32_at(rest, 8) as usize;
+        let content = rest
+            .get(12..12 + content_len)
+            .ok_or_else(|| invalid("truncated .vox chunk"))?;
+        match id {
+            //Its children follow right after, so they're walked like siblings
+            b"MAIN" => {
+                rest = &rest[12 + content_len..];
+                continue;
+            }
+            b"SIZE" if size.is_none() && content.len() >= 12 => {
+                size = Some((u32_at(content, 0), u32_at(content, 4), u32_at(content, 8)));
+            }
+            b"XYZI" if voxels.is_none() && content.len() >= 4 => {
+                let count = u32_at(content, 0) as usize;
+                voxels = Some(
+                    content
+                        .get(4..4 + count * 4)
+                        .ok_or_else(|| invalid("truncated voxel list"))?,
+                );
+            }
+            b"RGBA" if content.len() >= 1024 => palette = Some(content),
+            _ => {}
+        }
+        let skip = 12 + content_len + children_len;
+        rest = rest.get(skip..).unwrap_or(&[]);
+    }
+    let (sx, sy, sz) = size.ok_or_else(|| invalid("missing SIZE chunk"))?;
+    let voxels = voxels.ok_or_else(|| invalid("missing XYZI chunk"))?;
+    if sx as usize > MAX_SIZE || sy as usize > MAX_SIZE || sz as usize > MAX_SIZE {
+        return Err(invalid("model too large"));
+    }
+    //Palette entry `i` colours index `i + 1`, index 0 is empty
+    let mut blocks_of = [blocks::STONE; 256];
+    for (index, block) in blocks_of.iter_mut().enumerate().skip(1) {
+        *block = match palette {
+            Some(p) => {
+                let c = &p[(index - 1) * 4..index * 4];
+                nearest_block([c[0], c[1], c[2], c[3]])
+            }
+            None if blocks::get(index as blocks::BlockId).is_some() => index as blocks::BlockId,
+            None => blocks::STONE,
+        };
+    }
+    let len = Coord {
+        x: sx as usize,
+        y: sz as usize,
+        z: sy as usize,
+    };
+    let mut positions = vec![0; len.x * len.y * len.z];
+    for v in voxels.chunks(4) {
+        let (x, y, z) = (v[0] as usize, v[2] as usize, v[1] as usize);
+        if v[3] == 0 || x >= len.x || y >= len.y || z >= len.z {
+            continue;
+        }
+        positions[x + z * len.x + y * len.x * len.z] = blocks_of[v[3] as usize];
+    }
+    if positions.is_empty() {
+        return Ok(cubes::Cubes::new());
+    }
+    Ok(cubes::Cubes::from_dense(
+        positions,
+        Coord { x: 0, y: 0, z: 0 },
+        len,
+    ))
+}
+/// Writes `cubes` as a single model, its lowest corner at the model's origin.
+/// Colour index `i` is block ID `i`, with the palette holding each block's colour.
+pub fn write_vox<W: Write>(w: &mut W, cubes: &cubes::Cubes) -> io::Result<()> {
+    let size = cubes.size();
+    if size.x > MAX_SIZE || size.y > MAX_SIZE || size.z > MAX_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "world too large for a .vox model",
+        ));
+    }
+    let mut xyzi = vec![0; 4];
+    let mut count = 0u32;
+    for (idx, &id) in cubes.positions().iter().enumerate() {
+        if id == 0 {
+            continue;
+        }
+        let (x, y, z) = (idx % size.x, idx / (size.x * size.z), idx / size.x % size.z);
+        xyzi.extend_from_slice(&[x as u8, z as u8, y as u8, id]);
+        count += 1;
+    }
+    xyzi[..4].copy_from_slice(&count.to_le_bytes());
+    let mut dims = vec![];
+    for &v in &[size.x, size.z, size.y] {
+        dims.extend_from_slice(&(v as u32).to_le_bytes());
+    }
+    let mut rgba = vec![];
+    for index in 1..=256 {
+        rgba.extend_from_slice(&block_rgba(index.min(255) as blocks::BlockId));
+    }
+    let mut children = vec![];
+    for (id, content) in &[(b"SIZE", dims), (b"XYZI", xyzi), (b"RGBA", rgba)] {
+        write_chunk(&mut children, id, content, 0)?;
+    }
+    w.write_all(&MAGIC)?;
+    w.write_all(&VERSION.to_le_bytes())?;
+    w.write_all(b"MAIN")?;
+    w.write_all(&0u32.to_le_bytes())?;
+    w.write_all(&(children.len() as u32).to_le_bytes())?;
+    w.write_all(&children)
+}
+fn write_chunk<W: Write>(w: &mut W, id: &[u8; 4], content: &[u8], children: u32) -> io::Result<()> {
+    w.write_all(id)?;
+    w.write_all(&(content.len() as u32).to_le_bytes())?;
+    w.write_all(&children.to_le_bytes())?;
+    w.write_all(content)
+}
+pub fn load(path: &Path) -> io::Result<cubes::Cubes> {
+    read_vox(&mut BufReader::new(File::open(path)?))
+}
+pub fn save(path: &Path, cubes: &cubes::Cubes) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    write_vox(&mut w, cubes)?;
+    w.flush()
+}
+
+#[test]
+#[rustfmt::skip::macros(assert_eq)]
+fn vox_round_trip() {
+    let mut cubes = cubes::Cubes::new();
+    cubes.set(Coord { x: 0, y: 0, z: 0 }, blocks::GRASS);
+    cubes.set(Coord { x: 3, y: 1, z: 0 }, blocks::GLASS);
+    cubes.set(Coord { x: 1, y: 4, z: 2 }, blocks::WOOD);
+    let mut buf = vec![];
+    write_vox(&mut buf, &cubes).unwrap();
+    assert_eq!(&buf[..4], b"VOX ");
+    assert_eq!(read_vox(&mut io::Cursor::new(buf)).unwrap(), cubes);
+}
+#[test]
+#[rustfmt::skip::macros(assert_eq)]
+fn vox_axes_and_palette() {
+    //One red voxel at MagicaVoxel (1, 2, 3), so x = 1, up = 3, depth = 2
+    let mut children = vec![];
+    write_chunk(
+        &mut children,
+        b"SIZE",
+        &[2, 0, 0, 0, 3, 0, 0, 0, 4, 0, 0, 0],
+        0,
+    )
+    .unwrap();
+    write_chunk(&mut children, b"XYZI", &[1, 0, 0, 0, 1, 2, 3, 5], 0).unwrap();
+    let mut rgba = vec![0; 1024];
+    rgba[16..20].copy_from_slice(&[0x40, 0xA0, 0x30, 0xFF]);
+    write_chunk(&mut children, b"RGBA", &rgba, 0).unwrap();
+    let mut buf = b"VOX ".to_vec();
+    buf.extend_from_slice(&VERSION.to_le_bytes());
+    write_chunk(&mut buf, b"MAIN", &[], children.len() as u32).unwrap();
+    buf.extend_from_slice(&children);
+    let cubes = read_vox(&mut io::Cursor::new(buf)).unwrap();
+    assert_eq!(cubes.size(), Coord { x: 2, y: 4, z: 3 });
+    assert_eq!(cubes.get(Coord { x: 1, y: 3, z: 2 }), blocks::GRASS);
+    assert!(read_vox(&mut io::Cursor::new(b"VOX \x96\0\0\0".to_vec())).is_err());
+    assert_eq!(nearest_block([128, 128, 128, 255]), blocks::STONE);
+}
@@ -1,8 +1,10 @@
 #[macro_use]
 mod utils;
 mod app;
-use clap::clap_app;
-use std::process::exit;
+use clap::{clap_app, ArgMatches};
+#[cfg(windows)]
+use std::path::PathBuf;
+use std::{path::Path, process::exit};
 
 fn main() {
     let result = {
@@ -27,38 +29,198 @@ fn main() {
                 "Set window height")
             (@arg FULLSCREEN: -f --fullscreen conflicts_with[MAXMIZED] "Start in fullscreen")
             (@arg MAXIMIZED: -m --maximized "Start maximized")
+            (@arg SEED: -s --seed +takes_value {is_seed} "Generate terrain from this seed")
+            (@arg WORLD:
+                --world
+                +takes_value
+                "World file, loaded if it exists unless a new world is asked for, saved on exit and with F5 (default: world.vxw)")
+            (@arg VOX: --vox +takes_value conflicts_with[SEED HEIGHTMAP] "Start from a MagicaVoxel .vox model")
+            (@arg HEIGHTMAP:
+                --heightmap
+                +takes_value
+                conflicts_with[SEED]
+                "Raise terrain from a greyscale PNG, brighter is higher")
+            (@arg HEIGHT_SCALE:
+                --("height-scale")
+                +takes_value
+                {is_scale}
+                requires[HEIGHTMAP]
+                "Blocks between black and white in the heightmap (default: 32)")
+            (@arg LAYERS:
+                --layers
+                +takes_value
+                {is_layers}
+                requires[HEIGHTMAP]
+                "Heightmap blocks from the top down (default: grass:1,dirt:3,stone)")
+            (@arg BINDINGS:
+                --bindings
+                +takes_value
+                "Key bindings file, lines like `MoveForward = W, Up`")
+            (@arg SENSITIVITY:
+                --sensitivity
+                +takes_value
+                {is_scale}
+                "Radians turned per mouse count (default: 0.002)")
+            (@arg INVERT_Y: --("invert-y") "Moving the mouse up looks down")
+            (@arg SMOOTHING:
+                --smoothing
+                +takes_value
+                {is_smoothing}
+                "Mouse-look smoothing from 0 (off) to just under 1 (default: 0)")
+            (@arg RECORD: --record +takes_value "Record the input stream to this file")
+            (@arg REPLAY:
+                --replay
+                +takes_value
+                "Play back a recording from the world it started in, needs the same bindings and camera settings")
+            (@subcommand export =>
+                (about: "Convert a world without opening the window")
+                (@arg INPUT: +required "World file, .vxw or .vox")
+                (@arg OUTPUT: +required "Output file, .obj, .glb or .vox")
+                (@arg NAIVE: --naive "One quad per block face instead of merging them"))
+            (@subcommand map =>
+                (about: "Render a top-down map of a world to PNG without opening the window")
+                (@arg OUTPUT: +required "PNG to write")
+                (@arg WORLD: --world +takes_value required_unless[SEED] "World file, .vxw or .vox")
+                (@arg SEED: -s --seed +takes_value {is_seed} conflicts_with[WORLD] "Generate terrain from this seed")
+                (@arg SHADE: --shade "Brighten higher columns"))
         }
         .get_matches();
-        let state = if clap_app.is_present("FULLSCREEN") {
-            app::WindowState::Fullscreen
-        } else if clap_app.is_present("MAXIMIZED") {
-            app::WindowState::Maximized
-        } else if let (Some(w), Some(h)) = (clap_app.value_of("WIDTH"), clap_app.value_of("HEIGHT"))
-        {
-            app::WindowState::Windowed(w.parse().unwrap(), h.parse().unwrap())
-        } else {
-            app::WindowState::Windowed(800, 600)
-        };
-        let vsync = clap_app
-            .value_of("VSYNC")
-            .unwrap_or("true")
-            .parse::<bool>()
-            .unwrap();
-        let flags = app::Flags { state, vsync };
-        let class_name = "com.game.dx11";
-        match app::App::init_application(flags, class_name) {
-            Ok(x) => {
-                let h_wnd = x;
-                match app::App::init(flags, class_name, h_wnd) {
-                    Ok(mut a) => a.run(),
-                    Err(e) => e,
-                }
-            }
-            Err(e) => e,
+        if let Some(export) = clap_app.subcommand_matches("export") {
+            let meshing = if export.is_present("NAIVE") {
+                app::Meshing::Naive
+            } else {
+                app::Meshing::Greedy
+            };
+            let input = Path::new(export.value_of("INPUT").unwrap());
+            let output = Path::new(export.value_of("OUTPUT").unwrap());
+            exit(
+                match app::export_file(&app::World::from_file(input), output, meshing) {
+                    Ok(()) => 0,
+                    Err(e) => {
+                        eprintln!("Failed to export {}: {}", input.display(), e);
+                        app::io_error(e)
+                    }
+                },
+            );
+        }
+        if let Some(map) = clap_app.subcommand_matches("map") {
+            let world = match map.value_of("SEED") {
+                Some(seed) => app::World::Generated(seed.parse().unwrap()),
+                None => app::World::from_file(Path::new(map.value_of("WORLD").unwrap())),
+            };
+            let output = Path::new(map.value_of("OUTPUT").unwrap());
+            exit(
+                match app::map_file(&world, output, map.is_present("SHADE")) {
+                    Ok(()) => 0,
+                    Err(e) => {
+                        eprintln!("Failed to render map: {}", e);
+                        app::io_error(e)
+                    }
+                },
+            );
         }
+        play(&clap_app)
     };
     exit(result);
 }
+/// Opens the window and runs the game until it's closed, returns the exit code.
+#[cfg(windows)]
+fn play(clap_app: &ArgMatches) -> i32 {
+    let state = if clap_app.is_present("FULLSCREEN") {
+        app::WindowState::Fullscreen
+    } else if clap_app.is_present("MAXIMIZED") {
+        app::WindowState::Maximized
+    } else if let (Some(w), Some(h)) = (clap_app.value_of("WIDTH"), clap_app.value_of("HEIGHT")) {
+        app::WindowState::Windowed(w.parse().unwrap(), h.parse().unwrap())
+    } else {
+        app::WindowState::Windowed(800, 600)
+    };
+    let vsync = clap_app
+        .value_of("VSYNC")
+        .unwrap_or("true")
+        .parse::<bool>()
+        .unwrap();
+    let flags = app::Flags { state, vsync };
+    let save_path = PathBuf::from(clap_app.value_of("WORLD").unwrap_or(app::DEFAULT_WORLD));
+    //Asking for a new world starts one, otherwise the save is picked up again
+    let new_world = ["VOX", "HEIGHTMAP", "SEED"]
+        .iter()
+        .any(|arg| clap_app.is_present(arg));
+    let saved = app::World::existing_save(&save_path);
+    if new_world && saved.is_some() {
+        eprintln!(
+            "Starting a new world, saving it will replace {}",
+            save_path.display()
+        );
+    }
+    let world = if let Some(saved) = saved.filter(|_| !new_world) {
+        saved
+    } else if let Some(path) = clap_app.value_of("VOX") {
+        app::World::Vox(PathBuf::from(path))
+    } else if let Some(path) = clap_app.value_of("HEIGHTMAP") {
+        let mut settings = app::HeightmapSettings::default();
+        if let Some(scale) = clap_app.value_of("HEIGHT_SCALE") {
+            settings.scale = scale.parse().unwrap();
+        }
+        if let Some(layers) = clap_app.value_of("LAYERS") {
+            settings.parse_layers(layers).unwrap();
+        }
+        app::World::Heightmap(PathBuf::from(path), settings)
+    } else if let Some(seed) = clap_app.value_of("SEED") {
+        app::World::Generated(seed.parse().unwrap())
+    } else {
+        app::World::Demo
+    };
+    let bindings = match clap_app.value_of("BINDINGS") {
+        Some(path) => match app::Bindings::load(Path::new(path)) {
+            Ok(bindings) => bindings,
+            Err(e) => {
+                eprintln!("Failed to load bindings from {}: {}", path, e);
+                exit(app::io_error(e));
+            }
+        },
+        None => app::Bindings::default(),
+    };
+    let mut camera = app::CameraSettings::default();
+    if let Some(sensitivity) = clap_app.value_of("SENSITIVITY") {
+        camera.sensitivity = sensitivity.parse().unwrap();
+    }
+    if let Some(smoothing) = clap_app.value_of("SMOOTHING") {
+        camera.smoothing = smoothing.parse().unwrap();
+    }
+    camera.invert_y = clap_app.is_present("INVERT_Y");
+    let record = clap_app.value_of("RECORD").map(PathBuf::from);
+    let replay = clap_app.value_of("REPLAY").map(|path| {
+        app::load_replay(Path::new(path)).unwrap_or_else(|e| {
+            eprintln!("Failed to load recording {}: {}", path, e);
+            exit(app::io_error(e));
+        })
+    });
+    let options = app::Options {
+        world,
+        save_path,
+        bindings,
+        camera,
+        record,
+        replay,
+    };
+    let class_name = "com.game.dx11";
+    match app::App::init_application(flags, class_name) {
+        Ok(x) => {
+            let h_wnd = x;
+            match app::App::init(flags, class_name, h_wnd, options) {
+                Ok(mut a) => a.run(),
+                Err(e) => e,
+            }
+        }
+        Err(e) => e,
+    }
+}
+#[cfg(not(windows))]
+fn play(_: &ArgMatches) -> i32 {
+    eprintln!("The game window needs Windows, only the export and map subcommands work here");
+    1
+}
 fn is_bool(v: String) -> Result<(), String> {
     if v == "true" || v == "false" {
         Ok(())
@@ -72,3 +234,24 @@ fn is_num(v: String) -> Result<(), String> {
         Err(_) => Err(String::from("Must be integer")),
     }
 }
+fn is_seed(v: String) -> Result<(), String> {
+    match v.parse::<u64>() {
+        Ok(_) => Ok(()),
+        Err(_) => Err(String::from("Must be a positive integer")),
+    }
+}
+fn is_scale(v: String) -> Result<(), String> {
+    match v.parse::<f32>() {
+        Ok(x) if x >= 0.0 => Ok(()),
+        _ => Err(String::from("Must be a positive number")),
+    }
+}
+fn is_smoothing(v: String) -> Result<(), String> {
+    match v.parse::<f32>() {
+        Ok(x) if (0.0..1.0).contains(&x) => Ok(()),
+        _ => Err(String::from("Must be at least 0 and less than 1")),
+    }
+}
+fn is_layers(v: String) -> Result<(), String> {
+    app::HeightmapSettings::default().parse_layers(&v)
+}
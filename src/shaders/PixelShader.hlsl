@@ -1,6 +1,13 @@
 struct PixelShaderInput {
     float4 color: COLOR;
+    float3 normal: NORMAL;
+    float2 uv: TEXCOORD;
+    float ao: AO;
+    nointerpolation uint block: BLOCKID;
 };
+static const float3 lightDirection = normalize(float3(0.4f, 1.0f, -0.6f));
 float4 ShaderMain(PixelShaderInput IN): SV_TARGET {
-    return IN.color;
+    float light = 0.55f + 0.45f * saturate(dot(normalize(IN.normal), lightDirection));
+    light *= lerp(0.45f, 1.0f, IN.ao);
+    return float4(IN.color.rgb * light, IN.color.a);
 }
@@ -9,9 +9,18 @@ cbuffer PerObject: register(b2) {
 }
 struct AppData {
     float3 position: POSITION;
+    float ao: AO;
+    float3 normal: NORMAL;
+    float2 uv: TEXCOORD;
+    float4 color: COLOR;
+    uint block: BLOCKID;
 };
 struct VertexShaderOutput {
     float4 color: COLOR;
+    float3 normal: NORMAL;
+    float2 uv: TEXCOORD;
+    float ao: AO;
+    nointerpolation uint block: BLOCKID;
     float4 position: SV_POSITION;
 };
 VertexShaderOutput ShaderMain(AppData IN) {
@@ -19,7 +28,11 @@ VertexShaderOutput ShaderMain(AppData IN) {
  
     matrix mvp = mul(projectionMatrix, mul(viewMatrix, worldMatrix));
     OUT.position = mul(mvp, float4(IN.position, 1.0f));
-    OUT.color = float4(0.8f, 0.8f, 0.8f, 1.0f);
+    OUT.normal = normalize(mul((float3x3)worldMatrix, IN.normal));
+    OUT.uv = IN.uv;
+    OUT.color = IN.color;
+    OUT.ao = IN.ao / 3.0f;
+    OUT.block = IN.block;
  
     return OUT;
-}
\ No newline at end of file
+}
@@ -1,10 +1,16 @@
 use core::fmt;
-use std::{ffi::OsStr, iter, ops, os::windows::prelude::OsStrExt};
+#[cfg(windows)]
+use std::{ffi::OsStr, iter, os::windows::prelude::OsStrExt};
+use std::{
+    io::{self, Read},
+    ops,
+};
 
 /*use directx_math::{
     XMVectorGetX, XMVectorGetY, XMVectorGetZ, XMVectorScale, XMMATRIX, XMVECTOR, XMVECTORF32,
 };*/
 
+#[cfg(windows)]
 pub fn str_to_c16<T>(string: &T) -> Vec<u16>
 where
     T: std::convert::AsRef<std::ffi::OsStr> + ?Sized,
@@ -14,6 +20,7 @@ where
         .chain(iter::once(0))
         .collect::<Vec<u16>>()
 }
+#[cfg(windows)]
 pub fn win32_to_hresult(code: u32) -> i32 {
     if code as i32 <= 0 {
         code as i32
@@ -40,6 +47,38 @@ pub fn read_key(code: u16, keys: [u16; 16]) -> bool {
     let slot = code / 16;
     let bit = code % 16;
     keys[slot as usize] & 1 << bit > 0
+}
+/// Error for a file that doesn't hold what its format says.
+pub fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+pub fn read_array<R: Read, const N: usize>(r: &mut R) -> io::Result<[u8; N]> {
+    let mut buf = [0; N];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+pub fn read_u16<R: Read>(r: &mut R) -> io::Result<u16> {
+    Ok(u16::from_le_bytes(read_array(r)?))
+}
+pub fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    Ok(u32::from_le_bytes(read_array(r)?))
+}
+/// Little endian `u32` at byte `at` of `data`.
+pub fn u32_at(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
+/// Keeps the I/O error underneath, anything else means the PNG is broken.
+pub fn png_decoding_error(e: png::DecodingError) -> io::Error {
+    match e {
+        png::DecodingError::IoError(x) => x,
+        x => io::Error::new(io::ErrorKind::InvalidData, x.to_string()),
+    }
+}
+pub fn png_encoding_error(e: png::EncodingError) -> io::Error {
+    match e {
+        png::EncodingError::IoError(x) => x,
+        x => io::Error::new(io::ErrorKind::InvalidData, x.to_string()),
+    }
 } /*
   pub fn mat_to_quat(mat: XMMATRIX) -> XMVECTOR {
       unsafe {
@@ -84,7 +123,8 @@ pub fn read_key(code: u16, keys: [u16; 16]) -> bool {
           XMVectorScale(q.v, 0.5 / f32::sqrt(t))
       }
   }*/
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Coord<T> {
     pub x: T,
     pub y: T,
@@ -102,6 +142,30 @@ impl<T: ops::Add<Output = T>> ops::Add<Self> for Coord<T> {
     }
 }
 
+/// Axis access by number, 0 is x, 1 is y and 2 is z.
+impl<T> ops::Index<usize> for Coord<T> {
+    type Output = T;
+    fn index(&self, axis: usize) -> &T {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("axis {} out of range", axis),
+        }
+    }
+}
+
+impl<T> ops::IndexMut<usize> for Coord<T> {
+    fn index_mut(&mut self, axis: usize) -> &mut T {
+        match axis {
+            0 => &mut self.x,
+            1 => &mut self.y,
+            2 => &mut self.z,
+            _ => panic!("axis {} out of range", axis),
+        }
+    }
+}
+
 impl<T: fmt::Display> fmt::Display for Coord<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "x: {}, y: {}, z: {}", self.x, self.y, self.z)